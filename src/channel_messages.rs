@@ -57,4 +57,9 @@ pub enum OperationRequest {
         handler_name: String,
         tx: Sender<Result<()>>,
     },
+    KeyvalueChanged {
+        handler_name: String,
+        key: String,
+        kind: crate::keyvalue_handler::ChangeKind,
+    },
 }
@@ -0,0 +1,135 @@
+use crate::{
+    channel_messages::OperationRequest,
+    host_state::HostState,
+    http_handler::{build_http_handler, HttpHandler},
+    keyvalue_handler::{build_keyvalue_handler, KeyvalueHandler},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Receiver;
+use log::{debug, error, warn};
+use std::collections::HashMap;
+
+/// Run the handlers of the WebAssembly module, as requested by the other
+/// threads. The module is not thread safe, hence all the handlers are run
+/// one after the other by this loop.
+pub(crate) fn run_event_loop(
+    rx: &Receiver<OperationRequest>,
+    instance: &wasmi::Instance,
+    store: &mut wasmi::Store<HostState>,
+) -> Result<()> {
+    let mut http_handlers: HashMap<String, HttpHandler<HostState>> = HashMap::new();
+    let mut keyvalue_handlers: HashMap<String, KeyvalueHandler<HostState>> = HashMap::new();
+
+    loop {
+        match rx.recv() {
+            Ok(req) => {
+                debug!("Got something to do: {:?}", req);
+                match req {
+                    OperationRequest::RegisterHttpHandler { handler_name, tx } => {
+                        debug!("registering http handler with name: '{}'", handler_name);
+
+                        let res = if let std::collections::hash_map::Entry::Vacant(e) =
+                            http_handlers.entry(handler_name.clone())
+                        {
+                            debug!(
+                                "looking for '{}' handler inside of wasm module",
+                                handler_name
+                            );
+
+                            match build_http_handler(&handler_name, instance, &mut *store) {
+                                Ok(h) => {
+                                    e.insert(h);
+                                    Ok(())
+                                }
+                                Err(e) => Err(anyhow!("Cannot find handler: {}", e)),
+                            }
+                        } else {
+                            debug!("'{}' handler is already known", handler_name);
+                            Ok(())
+                        };
+                        if let Err(e) = tx.try_send(res) {
+                            error!("channel communication error: {}", e);
+                        };
+                    }
+                    OperationRequest::InvokeHttpHandler {
+                        handler_name,
+                        http_req,
+                        tx,
+                    } => {
+                        let res = match http_handlers.get(&handler_name) {
+                            None => {
+                                warn!("Cannot find handler with name: {}", handler_name);
+                                Err(crate::http_handler::HttpError::StatusError(400))
+                            }
+                            Some(handler) => {
+                                debug!("invoking http handler '{}'", handler_name);
+                                let mut headers: Vec<(&str, &str)> = vec![];
+                                for (k, v) in &http_req.headers {
+                                    headers.push((k.as_str(), v.as_str()));
+                                }
+
+                                let mut params: Vec<(&str, &str)> = vec![];
+                                for (k, v) in &http_req.params {
+                                    params.push((k.as_str(), v.as_str()));
+                                }
+
+                                let body = http_req.body.as_deref();
+
+                                let handler_req = crate::http_handler::Request {
+                                    method: http_req.method,
+                                    uri: &http_req.uri,
+                                    headers: &headers,
+                                    params: &params,
+                                    body,
+                                };
+
+                                match handler.handle_http(&mut *store, handler_req) {
+                                    Err(e) => {
+                                        error!("http handler wasm error: {}", e);
+                                        Err(crate::http_handler::HttpError::StatusError(500))
+                                    }
+                                    Ok(r) => {
+                                        debug!("'{}' provided response", handler_name);
+                                        r
+                                    }
+                                }
+                            }
+                        };
+                        if let Err(e) = tx.try_send(res) {
+                            error!("channel communication error: {}", e);
+                        };
+                    }
+                    OperationRequest::KeyvalueChanged {
+                        handler_name,
+                        key,
+                        kind,
+                    } => {
+                        // the handlers are looked up the first time they are
+                        // needed, since keys can be watched at any time
+                        let handler = match keyvalue_handlers.entry(handler_name.clone()) {
+                            std::collections::hash_map::Entry::Occupied(e) => e.into_mut(),
+                            std::collections::hash_map::Entry::Vacant(e) => {
+                                match build_keyvalue_handler(&handler_name, instance, &mut *store) {
+                                    Ok(h) => e.insert(h),
+                                    Err(e) => {
+                                        warn!("Cannot find keyvalue handler: {}", e);
+                                        continue;
+                                    }
+                                }
+                            }
+                        };
+
+                        debug!("invoking keyvalue handler '{}'", handler_name);
+                        if let Err(e) = handler.handle_change(&mut *store, &key, kind) {
+                            error!("keyvalue handler wasm error: {}", e);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Error trying to receive message from channel: {}", e);
+            }
+        }
+    }
+}
@@ -1,15 +1,19 @@
+use crate::channel_messages::OperationRequest;
 use crate::http_handler::{HttpHandlerData, HttpState};
 use crate::http_server::{http_server, HttpServerContext, HttpServerInner};
-use crate::keyvalue::{keyvalue, redis::RedisKeyvalueContext};
+use crate::keyvalue::{keyvalue, KeyvalueContext};
+use crate::keyvalue_handler::{KeyvalueHandlerData, KeyvalueHandlerState};
 use crate::settings::Settings;
 
 use anyhow::Result;
+use crossbeam_channel::Sender;
 use wasmi::Linker;
 
 pub(crate) struct HostState {
-    redis_ctx: RedisKeyvalueContext,
+    keyvalue_ctx: KeyvalueContext,
     http_server_ctx: HttpServerContext,
     http_handler_data: HttpHandlerData,
+    keyvalue_handler_data: KeyvalueHandlerData,
 }
 
 impl HttpState for HostState {
@@ -19,22 +23,29 @@ impl HttpState for HostState {
     }
 }
 
+impl KeyvalueHandlerState for HostState {
+    /// Get the mutable reference to the keyvalue handler data.
+    fn get_keyvalue_handler_state_mut(&mut self) -> &mut KeyvalueHandlerData {
+        &mut self.keyvalue_handler_data
+    }
+}
+
 impl HostState {
-    pub(crate) fn new(settings: &Settings) -> Result<Self> {
-        let redis_ctx =
-            RedisKeyvalueContext::new(&settings.redis_host, settings.redis_thread_pool_size)?;
+    pub(crate) fn new(settings: &Settings, event_tx: Sender<OperationRequest>) -> Result<Self> {
+        let keyvalue_ctx = KeyvalueContext::new(settings, event_tx)?;
         let http_server_ctx = HttpServerContext::new()?;
 
         Ok(Self {
-            redis_ctx,
+            keyvalue_ctx,
             http_server_ctx,
             http_handler_data: HttpHandlerData::default(),
+            keyvalue_handler_data: KeyvalueHandlerData::default(),
         })
     }
 
     pub(crate) fn add_to_linker(linker: &mut Linker<Self>) -> Result<()> {
         keyvalue::add_to_linker(linker, |ctx: &mut HostState| {
-            (&mut ctx.redis_ctx.kv, &mut ctx.redis_ctx.table)
+            (&mut ctx.keyvalue_ctx.kv, &mut ctx.keyvalue_ctx.table)
         })?;
 
         http_server::add_to_linker(linker, |ctx: &mut HostState| {
@@ -49,4 +60,8 @@ impl HostState {
     pub(crate) fn server(&self) -> Option<HttpServerInner> {
         self.http_server_ctx.server.inner.clone()
     }
+
+    pub(crate) fn keyvalue_watching(&self) -> bool {
+        self.keyvalue_ctx.kv.is_watching()
+    }
 }
@@ -3,17 +3,15 @@ wit_bindgen_wasmi::export!({paths: ["wit/http-server.wit"]});
 mod router;
 mod server;
 
-use crate::host_state::HostState;
-use crate::http_handler::{build_http_handler, HttpHandler};
 use crate::{channel_messages::OperationRequest, settings::Settings};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use http_server::{HttpRouterError, HttpServerTables, Uri};
-use log::{debug, error, info, warn};
+use log::{debug, info};
 use parking_lot::RwLock;
 use router::RouterInner;
 use server::WasmHttpServer;
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct HttpServerInner {
@@ -111,6 +109,58 @@ impl http_server::HttpServer for HttpServerImplementor {
         rclone.delete(route.to_string(), handler.to_string())
     }
 
+    /// register a HTTP PATCH route
+    fn router_patch(
+        &mut self,
+        router: &Self::Router,
+        route: &str,
+        handler: &str,
+    ) -> Result<Self::Router, HttpRouterError> {
+        // Router is a reference to the router proxy, so we need to clone it to get a
+        // mutable reference to the router.
+        let mut rclone = router.clone();
+        rclone.patch(route.to_string(), handler.to_string())
+    }
+
+    /// register a HTTP HEAD route
+    fn router_head(
+        &mut self,
+        router: &Self::Router,
+        route: &str,
+        handler: &str,
+    ) -> Result<Self::Router, HttpRouterError> {
+        // Router is a reference to the router proxy, so we need to clone it to get a
+        // mutable reference to the router.
+        let mut rclone = router.clone();
+        rclone.head(route.to_string(), handler.to_string())
+    }
+
+    /// register a HTTP OPTIONS route
+    fn router_options(
+        &mut self,
+        router: &Self::Router,
+        route: &str,
+        handler: &str,
+    ) -> Result<Self::Router, HttpRouterError> {
+        // Router is a reference to the router proxy, so we need to clone it to get a
+        // mutable reference to the router.
+        let mut rclone = router.clone();
+        rclone.options(route.to_string(), handler.to_string())
+    }
+
+    /// register a route matching any HTTP method without a route of its own
+    fn router_any(
+        &mut self,
+        router: &Self::Router,
+        route: &str,
+        handler: &str,
+    ) -> Result<Self::Router, HttpRouterError> {
+        // Router is a reference to the router proxy, so we need to clone it to get a
+        // mutable reference to the router.
+        let mut rclone = router.clone();
+        rclone.any(route.to_string(), handler.to_string())
+    }
+
     /// create a new HTTP server and serve the given router
     fn server_serve(
         &mut self,
@@ -149,103 +199,13 @@ impl HttpServerContext {
     }
 }
 
-pub(crate) fn start_http_server_loop(
+/// Start serving the routes registered by the WebAssembly module. The
+/// handlers are invoked by the main event loop, through the given channel.
+pub(crate) fn start_http_server(
     http_inner_server: &HttpServerInner,
     settings: &Settings,
-    instance: &wasmi::Instance,
-    store: &mut wasmi::Store<HostState>,
+    tx: crossbeam_channel::Sender<OperationRequest>,
 ) -> Result<()> {
-    let mut http_handlers: HashMap<String, HttpHandler<HostState>> = HashMap::new();
-
-    let (tx, rx) = crossbeam_channel::bounded::<OperationRequest>(100);
-
     let server = WasmHttpServer::new(http_inner_server, tx);
-    server.serve(settings.http_server_worker_pool_size)?;
-
-    loop {
-        match rx.recv() {
-            Ok(req) => {
-                debug!("Got something to do: {:?}", req);
-                match req {
-                    OperationRequest::RegisterHttpHandler { handler_name, tx } => {
-                        debug!("registering http handler with name: '{}'", handler_name);
-
-                        let res = if let std::collections::hash_map::Entry::Vacant(e) =
-                            http_handlers.entry(handler_name.clone())
-                        {
-                            debug!(
-                                "looking for '{}' handler inside of wasm module",
-                                handler_name
-                            );
-
-                            match build_http_handler(&handler_name, instance, &mut *store) {
-                                Ok(h) => {
-                                    e.insert(h);
-                                    Ok(())
-                                }
-                                Err(e) => Err(anyhow!("Cannot find handler: {}", e)),
-                            }
-                        } else {
-                            debug!("'{}' handler is already known", handler_name);
-                            Ok(())
-                        };
-                        if let Err(e) = tx.try_send(res) {
-                            error!("channel communication error: {}", e);
-                        };
-                    }
-                    OperationRequest::InvokeHttpHandler {
-                        handler_name,
-                        http_req,
-                        tx,
-                    } => {
-                        let res = match http_handlers.get(&handler_name) {
-                            None => {
-                                warn!("Cannot find handler with name: {}", handler_name);
-                                Err(crate::http_handler::HttpError::StatusError(400))
-                            }
-                            Some(handler) => {
-                                debug!("invoking http handler '{}'", handler_name);
-                                let mut headers: Vec<(&str, &str)> = vec![];
-                                for (k, v) in &http_req.headers {
-                                    headers.push((k.as_str(), v.as_str()));
-                                }
-
-                                let mut params: Vec<(&str, &str)> = vec![];
-                                for (k, v) in &http_req.params {
-                                    params.push((k.as_str(), v.as_str()));
-                                }
-
-                                let body = http_req.body.as_deref();
-
-                                let handler_req = crate::http_handler::Request {
-                                    method: http_req.method,
-                                    uri: &http_req.uri,
-                                    headers: &headers,
-                                    params: &params,
-                                    body,
-                                };
-
-                                match handler.handle_http(&mut *store, handler_req) {
-                                    Err(e) => {
-                                        error!("http handler wasm error: {}", e);
-                                        Err(crate::http_handler::HttpError::StatusError(500))
-                                    }
-                                    Ok(r) => {
-                                        debug!("'{}' provided response", handler_name);
-                                        r
-                                    }
-                                }
-                            }
-                        };
-                        if let Err(e) = tx.try_send(res) {
-                            error!("channel communication error: {}", e);
-                        };
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Error trying to receive message from channel: {}", e);
-            }
-        }
-    }
+    server.serve(settings.http_server_worker_pool_size)
 }
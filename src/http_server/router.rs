@@ -11,6 +11,11 @@ pub enum Methods {
     PUT,
     POST,
     DELETE,
+    PATCH,
+    HEAD,
+    OPTIONS,
+    /// Any method without a route of its own
+    ANY,
 }
 
 #[derive(Clone, Debug)]
@@ -72,6 +77,42 @@ impl RouterInner {
         self.add(route, handler, Methods::DELETE)
     }
 
+    /// Adds a new route with `PATCH` method and the handler's name.
+    pub fn patch(
+        &mut self,
+        route: String,
+        handler: String,
+    ) -> Result<Self, http_server::HttpRouterError> {
+        self.add(route, handler, Methods::PATCH)
+    }
+
+    /// Adds a new route with `HEAD` method and the handler's name.
+    pub fn head(
+        &mut self,
+        route: String,
+        handler: String,
+    ) -> Result<Self, http_server::HttpRouterError> {
+        self.add(route, handler, Methods::HEAD)
+    }
+
+    /// Adds a new route with `OPTIONS` method and the handler's name.
+    pub fn options(
+        &mut self,
+        route: String,
+        handler: String,
+    ) -> Result<Self, http_server::HttpRouterError> {
+        self.add(route, handler, Methods::OPTIONS)
+    }
+
+    /// Adds a new route matching any method, and the handler's name.
+    pub fn any(
+        &mut self,
+        route: String,
+        handler: String,
+    ) -> Result<Self, http_server::HttpRouterError> {
+        self.add(route, handler, Methods::ANY)
+    }
+
     /// Adds a new route with the given method and the handler's name.
     pub fn add(
         &mut self,
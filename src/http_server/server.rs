@@ -21,14 +21,88 @@ impl TryFrom<tiny_http::Method> for crate::http_handler::Method {
     }
 }
 
-impl From<&Methods> for tiny_http::Method {
-    fn from(value: &Methods) -> tiny_http::Method {
-        match value {
-            Methods::GET => tiny_http::Method::Get,
-            Methods::PUT => tiny_http::Method::Put,
-            Methods::POST => tiny_http::Method::Post,
-            Methods::DELETE => tiny_http::Method::Delete,
+/// The tiny_http method of the route, none for the routes matching any method
+fn route_method(method: &Methods) -> Option<tiny_http::Method> {
+    match method {
+        Methods::GET => Some(tiny_http::Method::Get),
+        Methods::PUT => Some(tiny_http::Method::Put),
+        Methods::POST => Some(tiny_http::Method::Post),
+        Methods::DELETE => Some(tiny_http::Method::Delete),
+        Methods::PATCH => Some(tiny_http::Method::Patch),
+        Methods::HEAD => Some(tiny_http::Method::Head),
+        Methods::OPTIONS => Some(tiny_http::Method::Options),
+        Methods::ANY => None,
+    }
+}
+
+/// The path of the request URL, without the query string, which the routes
+/// must not depend on
+fn request_path(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(path, _)| path)
+}
+
+/// The routes served by a worker. The routes registered for any method are
+/// used only when the method of the request has no matching route of its own,
+/// while `HEAD` requests fall back to the `GET` routes first.
+#[derive(Default)]
+struct Routes {
+    by_method: HashMap<tiny_http::Method, route_recognizer::Router<String>>,
+    any: Option<route_recognizer::Router<String>>,
+}
+
+impl Routes {
+    /// The methods that can be used with the given path, sorted by name.
+    /// This includes `HEAD` when there's a `GET` route, and `OPTIONS`, which
+    /// are answered automatically.
+    fn allowed_methods(&self, path: &str) -> Vec<String> {
+        let mut methods: Vec<tiny_http::Method> = self
+            .by_method
+            .iter()
+            .filter(|(_, router)| router.recognize(path).is_ok())
+            .map(|(method, _)| method.clone())
+            .collect();
+        if methods.contains(&tiny_http::Method::Get) && !methods.contains(&tiny_http::Method::Head)
+        {
+            methods.push(tiny_http::Method::Head);
+        }
+        if !methods.is_empty() && !methods.contains(&tiny_http::Method::Options) {
+            methods.push(tiny_http::Method::Options);
         }
+
+        let mut methods: Vec<String> = methods.iter().map(|m| m.to_string()).collect();
+        methods.sort();
+        methods
+    }
+
+    fn recognize(
+        &self,
+        method: &tiny_http::Method,
+        path: &str,
+    ) -> Option<route_recognizer::Match<&String>> {
+        self.recognize_method(method, path)
+            .or_else(|| {
+                // tiny_http doesn't send the body of the responses to HEAD requests
+                if *method == tiny_http::Method::Head {
+                    self.recognize_method(&tiny_http::Method::Get, path)
+                } else {
+                    None
+                }
+            })
+            .or_else(|| {
+                self.any
+                    .as_ref()
+                    .and_then(|router| router.recognize(path).ok())
+            })
+    }
+
+    fn recognize_method(
+        &self,
+        method: &tiny_http::Method,
+        path: &str,
+    ) -> Option<route_recognizer::Match<&String>> {
+        self.by_method
+            .get(method)
+            .and_then(|router| router.recognize(path).ok())
     }
 }
 
@@ -128,31 +202,36 @@ impl WasmHttpServer {
                         Ok(r) => {
                             if let Some(mut req) = r {
                                 info!("[worker #{}] received request: {:?}", i + 1, req);
-                                let routes_by_method = routes.get(req.method());
-                                let response = match routes_by_method {
+                                let path = request_path(req.url());
+                                let response = match routes.recognize(req.method(), path) {
                                     None => {
-                                        let msg = "Bad request".as_bytes().to_vec();
-
-                                        tiny_http::Response::from_data(msg).with_status_code(400)
-                                    }
-                                    Some(router) => match router.recognize(req.url()) {
-                                        Err(e) => {
+                                        let allowed = routes.allowed_methods(path);
+                                        if allowed.is_empty() {
                                             warn!(
-                                                "[worker #{}] cannot find route for {} {}: {}",
+                                                "[worker #{}] cannot find route for {} {}",
                                                 i + 1,
                                                 req.method(),
-                                                req.url(),
-                                                e
+                                                req.url()
                                             );
                                             let msg = "Not found".as_bytes().to_vec();
                                             tiny_http::Response::from_data(msg)
                                                 .with_status_code(404)
+                                        } else if *req.method() == tiny_http::Method::Options {
+                                            options(&allowed)
+                                        } else {
+                                            warn!(
+                                                "[worker #{}] method {} not allowed for {}",
+                                                i + 1,
+                                                req.method(),
+                                                req.url()
+                                            );
+                                            method_not_allowed(&allowed)
                                         }
-                                        Ok(route_match) => {
-                                            info!("[worker #{}] route handler found", i + 1);
-                                            process_request(&mut req, &route_match, &wasm_eval_tx)
-                                        }
-                                    },
+                                    }
+                                    Some(route_match) => {
+                                        info!("[worker #{}] route handler found", i + 1);
+                                        process_request(&mut req, &route_match, &wasm_eval_tx)
+                                    }
                                 };
                                 info!("[worker #{}] sending http response", i + 1);
                                 if let Err(e) = req.respond(response) {
@@ -175,25 +254,39 @@ impl WasmHttpServer {
     }
 }
 
+fn allow_header(allowed: &[String]) -> tiny_http::Header {
+    tiny_http::Header::from_bytes("Allow".as_bytes(), allowed.join(", ").as_bytes())
+        .expect("Should not happen, method names are valid header values")
+}
+
+/// Build a 405 response, telling the client which methods can be used instead
+fn method_not_allowed(allowed: &[String]) -> tiny_http::Response<Cursor<Vec<u8>>> {
+    let msg = "Method not allowed".as_bytes().to_vec();
+    tiny_http::Response::from_data(msg)
+        .with_status_code(405)
+        .with_header(allow_header(allowed))
+}
+
+/// Build the response to an `OPTIONS` request that has no route of its own
+fn options(allowed: &[String]) -> tiny_http::Response<Cursor<Vec<u8>>> {
+    tiny_http::Response::from_data(vec![])
+        .with_status_code(204)
+        .with_header(allow_header(allowed))
+}
+
 fn build_routes(
     routes: &[crate::http_server::router::Route],
     wasm_eval_tx: &crossbeam_channel::Sender<OperationRequest>,
-) -> Result<HashMap<tiny_http::Method, route_recognizer::Router<String>>> {
-    let mut routes_map: HashMap<tiny_http::Method, route_recognizer::Router<String>> =
-        HashMap::new();
+) -> Result<Routes> {
+    let mut routes_map = Routes::default();
 
     for route in routes {
         debug!("adding route {:?}", route);
-        let method = tiny_http::Method::from(&route.method);
-        let route_recognizer = match routes_map.get_mut(&method) {
-            Some(r) => r,
-            None => {
-                let r = route_recognizer::Router::new();
-                routes_map.insert(method.clone(), r);
-                routes_map
-                    .get_mut(&method)
-                    .expect("Should not happen, the entry has just been added")
-            }
+        let route_recognizer = match route_method(&route.method) {
+            Some(method) => routes_map.by_method.entry(method).or_default(),
+            None => routes_map
+                .any
+                .get_or_insert_with(route_recognizer::Router::new),
         };
 
         let (tx, rx) = crossbeam_channel::bounded(1);
@@ -282,3 +375,139 @@ fn process_request(
         |r| r,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(routes: &[(Methods, &str)]) -> Routes {
+        let mut routes_map = Routes::default();
+        for (method, route) in routes {
+            let router = match route_method(method) {
+                Some(method) => routes_map.by_method.entry(method).or_default(),
+                None => routes_map
+                    .any
+                    .get_or_insert_with(route_recognizer::Router::new),
+            };
+            router.add(route, format!("{:?}", method).to_lowercase());
+        }
+        routes_map
+    }
+
+    fn handler(routes: &Routes, method: tiny_http::Method, path: &str) -> Option<String> {
+        routes
+            .recognize(&method, path)
+            .map(|route_match| route_match.handler().to_string())
+    }
+
+    fn allow(response: &tiny_http::Response<Cursor<Vec<u8>>>) -> Option<String> {
+        response
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Allow"))
+            .map(|h| h.value.to_string())
+    }
+
+    #[test]
+    fn query_string_is_not_part_of_the_path() {
+        assert_eq!(request_path("/items"), "/items");
+        assert_eq!(request_path("/items?x=1"), "/items");
+        assert_eq!(request_path("/items?x=1?y=2"), "/items");
+        assert_eq!(request_path("/?"), "/");
+
+        let routes = build(&[(Methods::GET, "/items")]);
+        let path = request_path("/items?x=1");
+        assert!(routes.recognize(&tiny_http::Method::Post, path).is_none());
+        assert_eq!(routes.allowed_methods(path), vec!["GET", "HEAD", "OPTIONS"]);
+    }
+
+    #[test]
+    fn unknown_path_has_no_allowed_methods() {
+        let routes = build(&[(Methods::GET, "/items"), (Methods::PUT, "/items/:id")]);
+        assert!(routes
+            .recognize(&tiny_http::Method::Get, "/users")
+            .is_none());
+        assert!(routes.allowed_methods("/users").is_empty());
+    }
+
+    #[test]
+    fn allowed_methods_are_sorted() {
+        let routes = build(&[
+            (Methods::PUT, "/items/:id"),
+            (Methods::GET, "/items/:id"),
+            (Methods::DELETE, "/items/:id"),
+            (Methods::POST, "/items"),
+        ]);
+        assert!(routes
+            .recognize(&tiny_http::Method::Post, "/items/1")
+            .is_none());
+        assert_eq!(
+            routes.allowed_methods("/items/1"),
+            vec!["DELETE", "GET", "HEAD", "OPTIONS", "PUT"]
+        );
+        assert_eq!(routes.allowed_methods("/items"), vec!["OPTIONS", "POST"]);
+
+        let response = method_not_allowed(&routes.allowed_methods("/items/1"));
+        assert_eq!(response.status_code(), 405);
+        assert_eq!(
+            allow(&response).as_deref(),
+            Some("DELETE, GET, HEAD, OPTIONS, PUT")
+        );
+    }
+
+    #[test]
+    fn method_routes_take_precedence_over_any() {
+        let routes = build(&[(Methods::ANY, "/items"), (Methods::POST, "/items")]);
+        assert_eq!(
+            handler(&routes, tiny_http::Method::Post, "/items").as_deref(),
+            Some("post")
+        );
+        assert_eq!(
+            handler(&routes, tiny_http::Method::Delete, "/items").as_deref(),
+            Some("any")
+        );
+        assert_eq!(
+            handler(&routes, tiny_http::Method::Options, "/items").as_deref(),
+            Some("any")
+        );
+    }
+
+    #[test]
+    fn head_falls_back_to_get() {
+        let routes = build(&[(Methods::GET, "/items"), (Methods::ANY, "/items")]);
+        assert_eq!(
+            handler(&routes, tiny_http::Method::Head, "/items").as_deref(),
+            Some("get")
+        );
+
+        let routes = build(&[(Methods::GET, "/items"), (Methods::HEAD, "/items")]);
+        assert_eq!(
+            handler(&routes, tiny_http::Method::Head, "/items").as_deref(),
+            Some("head")
+        );
+        assert_eq!(
+            routes.allowed_methods("/items"),
+            vec!["GET", "HEAD", "OPTIONS"]
+        );
+    }
+
+    #[test]
+    fn options_is_answered_automatically() {
+        let routes = build(&[(Methods::GET, "/items"), (Methods::POST, "/items")]);
+        assert!(routes
+            .recognize(&tiny_http::Method::Options, "/items")
+            .is_none());
+        let response = options(&routes.allowed_methods("/items"));
+        assert_eq!(response.status_code(), 204);
+        assert_eq!(
+            allow(&response).as_deref(),
+            Some("GET, HEAD, OPTIONS, POST")
+        );
+
+        let routes = build(&[(Methods::OPTIONS, "/items")]);
+        assert_eq!(
+            handler(&routes, tiny_http::Method::Options, "/items").as_deref(),
+            Some("options")
+        );
+    }
+}
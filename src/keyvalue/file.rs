@@ -0,0 +1,469 @@
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    increment_counter, keyvalue::KeyvalueError, round_to_secs, KeyvalueBackend, KeyvalueStore,
+};
+use crate::settings::Settings;
+
+/// Used to generate unique names for the temporary files
+static TMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Suffix of the files holding the moment a key expires, as milliseconds
+/// since the Unix epoch. Encoded names never contain a `.`, hence these
+/// files cannot be mistaken for keys.
+const EXPIRY_SUFFIX: &str = ".expires";
+
+/// Suffix of the files holding the key whose file name is a digest
+const KEY_SUFFIX: &str = ".key";
+
+/// Longest encoded name used as it is. Longer names are replaced by their
+/// digest, which keeps the names of all the files, including the temporary
+/// ones, within the 255 bytes allowed by most filesystems.
+const MAX_ENCODED_NAME_LEN: usize = 200;
+
+/// Prefix of the names made of a digest, `encode_name` never produces it
+const DIGEST_NAME_PREFIX: &str = "+";
+
+/// Turn a container name or a key into a safe file name.
+///
+/// ASCII letters, digits, `-` and `_` are kept as they are, all the other
+/// bytes are percent encoded. Because of that, the resulting name never
+/// contains path separators and never starts with a `.`, which is used
+/// to identify temporary files.
+fn encode_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Reverse the encoding done by `encode_name`
+fn decode_name(name: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(name.len());
+    let mut bytes = name.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            decoded.push(b);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Turn a container name or a key into the name of its file or directory
+fn file_name(name: &str) -> String {
+    let encoded = encode_name(name);
+    if encoded.len() <= MAX_ENCODED_NAME_LEN {
+        encoded
+    } else {
+        format!(
+            "{}{}",
+            DIGEST_NAME_PREFIX,
+            hex::encode(Sha256::digest(name.as_bytes()))
+        )
+    }
+}
+
+fn io_error(e: std::io::Error) -> KeyvalueError {
+    KeyvalueError::IoError(e.to_string())
+}
+
+/// Milliseconds elapsed since the Unix epoch
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+fn parse_expiry(content: &str) -> Result<u128, KeyvalueError> {
+    content
+        .trim()
+        .parse::<u128>()
+        .map_err(|e| KeyvalueError::IoError(format!("invalid expiry file: {}", e)))
+}
+
+pub struct FileDriver {
+    container_name: String,
+    path: PathBuf,
+    /// Serializes the read-modify-write operations
+    lock: Arc<Mutex<()>>,
+}
+
+impl fmt::Debug for FileDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileDriver")
+            .field("collection", &self.container_name)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl FileDriver {
+    fn new(container_name: &str, path: PathBuf, lock: Arc<Mutex<()>>) -> Self {
+        Self {
+            container_name: container_name.to_owned(),
+            path,
+            lock,
+        }
+    }
+
+    /// Like get, but a missing key is not an error
+    fn get_optional(&self, key: &str) -> Result<Option<Vec<u8>>, KeyvalueError> {
+        match self.keyvalue_get(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(KeyvalueError::KeyNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn key_path(&self, key: &str) -> PathBuf {
+        self.path.join(file_name(key))
+    }
+
+    fn expiry_path(&self, key: &str) -> PathBuf {
+        self.path
+            .join(format!("{}{}", file_name(key), EXPIRY_SUFFIX))
+    }
+
+    /// The path of the file holding the key, when its file name is a digest
+    fn key_name_path(&self, key: &str) -> Option<PathBuf> {
+        let name = file_name(key);
+        if name.starts_with(DIGEST_NAME_PREFIX) {
+            Some(self.path.join(format!("{}{}", name, KEY_SUFFIX)))
+        } else {
+            None
+        }
+    }
+
+    /// Write the payload of the key, together with the key itself when its
+    /// file name is a digest
+    fn write_value(&self, key: &str, value: &[u8]) -> Result<(), KeyvalueError> {
+        if let Some(path) = self.key_name_path(key) {
+            if !path.exists() {
+                self.write_file(key, path, key.as_bytes())?;
+            }
+        }
+        self.write_file(key, self.key_path(key), value)
+    }
+
+    /// Write the file through a temporary one, which is then renamed.
+    /// This ensures readers never see a partially written file.
+    fn write_file(&self, key: &str, path: PathBuf, content: &[u8]) -> Result<(), KeyvalueError> {
+        let tmp_path = self.path.join(format!(
+            ".tmp-{}-{}",
+            file_name(key),
+            TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let res = fs::File::create(&tmp_path)
+            .and_then(|mut f| {
+                f.write_all(content)?;
+                f.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, path));
+        if let Err(e) = res {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(io_error(e));
+        }
+        Ok(())
+    }
+
+    /// Find the time left before the given key expires.
+    ///
+    /// Expired keys are removed lazily, the first time they are accessed.
+    fn time_left(&self, key: &str) -> Result<Option<Duration>, KeyvalueError> {
+        let expires_at = match fs::read_to_string(self.expiry_path(key)) {
+            Ok(content) => parse_expiry(&content)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let now = now_millis();
+        if expires_at <= now {
+            self.keyvalue_delete(key)?;
+            return Err(KeyvalueError::KeyNotFound(key.to_owned()));
+        }
+        Ok(Some(Duration::from_millis((expires_at - now) as u64)))
+    }
+}
+
+impl KeyvalueStore for FileDriver {
+    /// get the payload for a given key
+    fn keyvalue_get(&self, key: &str) -> Result<Vec<u8>, KeyvalueError> {
+        debug!(key = key, container_name = self.container_name.as_str(); "file get key");
+        self.time_left(key)?;
+        fs::read(self.key_path(key)).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => KeyvalueError::KeyNotFound(key.to_owned()),
+            _ => io_error(e),
+        })
+    }
+
+    /// set the payload for a given key
+    fn keyvalue_set(&self, key: &str, value: &[u8]) -> Result<(), KeyvalueError> {
+        debug!("file set key");
+        self.write_value(key, value)?;
+        match fs::remove_file(self.expiry_path(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(io_error(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// set the payload for a given key, the key is deleted after ttl seconds
+    fn keyvalue_set_with_ttl(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: u64,
+    ) -> Result<(), KeyvalueError> {
+        debug!("file set key with ttl");
+        let expires_at = now_millis() + Duration::from_secs(ttl).as_millis();
+        // the expiry is written first, this way the new value is never
+        // seen together with the expiry of the old one
+        self.write_file(
+            key,
+            self.expiry_path(key),
+            expires_at.to_string().as_bytes(),
+        )?;
+        self.write_value(key, value)
+    }
+
+    /// get the seconds left before a given key expires, none if it never expires
+    fn keyvalue_get_ttl(&self, key: &str) -> Result<Option<u64>, KeyvalueError> {
+        debug!("file get ttl");
+        let time_left = self.time_left(key)?;
+        fs::metadata(self.key_path(key)).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => KeyvalueError::KeyNotFound(key.to_owned()),
+            _ => io_error(e),
+        })?;
+        Ok(time_left.map(round_to_secs))
+    }
+
+    /// atomically replace the payload for a given key, only if it matches the expected one
+    ///
+    /// This is atomic only within the unikernel, other processes accessing
+    /// the same directory are not taken into account.
+    fn keyvalue_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, KeyvalueError> {
+        debug!("file compare and swap");
+        let _guard = self.lock.lock();
+        if self.get_optional(key)?.as_deref() != expected {
+            return Ok(false);
+        }
+        self.keyvalue_set(key, new)?;
+        Ok(true)
+    }
+
+    /// atomically add delta to the integer stored at a given key, returning the result
+    ///
+    /// This is atomic only within the unikernel, other processes accessing
+    /// the same directory are not taken into account.
+    fn keyvalue_increment(&self, key: &str, delta: i64) -> Result<i64, KeyvalueError> {
+        debug!("file increment");
+        let _guard = self.lock.lock();
+        let counter = increment_counter(self.get_optional(key)?.as_deref(), delta)?;
+        // like Redis, the expiry of the key is kept
+        self.write_value(key, counter.to_string().as_bytes())?;
+        Ok(counter)
+    }
+
+    /// list the keys in the store
+    fn keyvalue_keys(&self) -> Result<Vec<String>, KeyvalueError> {
+        debug!("file keys");
+        let mut keys = vec![];
+        let mut expiries = HashMap::new();
+        for entry in fs::read_dir(&self.path).map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                // skip temporary files
+                Some(n) if !n.starts_with('.') => n,
+                _ => continue,
+            };
+            if let Some(name) = name.strip_suffix(EXPIRY_SUFFIX) {
+                let content = fs::read_to_string(entry.path()).map_err(io_error)?;
+                expiries.insert(name.to_string(), parse_expiry(&content)?);
+            } else if name.ends_with(KEY_SUFFIX) {
+                continue;
+            } else if name.starts_with(DIGEST_NAME_PREFIX) {
+                let key_name_path = self.path.join(format!("{}{}", name, KEY_SUFFIX));
+                match fs::read_to_string(key_name_path) {
+                    Ok(key) => keys.push((name.to_string(), key)),
+                    // the key is being deleted
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(io_error(e)),
+                }
+            } else if let Some(key) = decode_name(name) {
+                keys.push((name.to_string(), key));
+            }
+        }
+
+        // leave out the expired keys, they are removed when accessed
+        let now = now_millis();
+        Ok(keys
+            .into_iter()
+            .filter(|(name, _)| {
+                expiries
+                    .get(name)
+                    .map_or(true, |expires_at| *expires_at > now)
+            })
+            .map(|(_, key)| key)
+            .collect())
+    }
+
+    /// delete the payload for a given key
+    fn keyvalue_delete(&self, key: &str) -> Result<(), KeyvalueError> {
+        debug!("file delete key");
+        // the key itself is deleted last, this way it's always there when
+        // its payload is listed
+        let paths = [
+            Some(self.key_path(key)),
+            Some(self.expiry_path(key)),
+            self.key_name_path(key),
+        ];
+        for path in paths.into_iter().flatten() {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(io_error(e)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stores each container as a directory, with one file per key
+pub struct FileImplementor {
+    root: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl FileImplementor {
+    pub fn new(root: &str) -> Result<Self> {
+        info!("storing keyvalue data under {}", root);
+        let root = Path::new(root);
+        fs::create_dir_all(root)
+            .map_err(|e| anyhow!("cannot create directory {}: {}", root.display(), e))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            lock: Arc::default(),
+        })
+    }
+}
+
+impl KeyvalueBackend for FileImplementor {
+    fn keyvalue_open(&mut self, name: &str) -> Result<Box<dyn KeyvalueStore>, KeyvalueError> {
+        let path = self.root.join(file_name(name));
+        fs::create_dir_all(&path).map_err(io_error)?;
+        Ok(Box::new(FileDriver::new(name, path, self.lock.clone())))
+    }
+}
+
+pub fn build_backend(
+    location: Option<&str>,
+    settings: &Settings,
+) -> Result<Box<dyn KeyvalueBackend>> {
+    let root = location
+        .or(settings.keyvalue_file_root.as_deref())
+        .ok_or_else(|| {
+            anyhow!("The directory used by the file keyvalue backend must be provided")
+        })?;
+    Ok(Box::new(FileImplementor::new(root)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A backend storing its data inside of a new temporary directory
+    fn backend(name: &str) -> FileImplementor {
+        let root = std::env::temp_dir().join(format!(
+            "hermit-wasm-file-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        FileImplementor::new(root.to_str().unwrap()).unwrap()
+    }
+
+    fn sorted_keys(store: &dyn KeyvalueStore) -> Vec<String> {
+        let mut keys = store.keyvalue_keys().unwrap();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for name in ["key", "a/b", "../x", ".hidden", "100%", "ключ", ""] {
+            let encoded = encode_name(name);
+            assert!(!encoded.contains('/') && !encoded.contains('.'));
+            assert_eq!(decode_name(&encoded).as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn long_names_fit_the_filesystem() {
+        assert_eq!(file_name(&"a".repeat(MAX_ENCODED_NAME_LEN)).len(), 200);
+        for name in ["a".repeat(250), "/".repeat(80), "a".repeat(1024)] {
+            let file_name = file_name(&name);
+            assert!(file_name.starts_with(DIGEST_NAME_PREFIX));
+            assert!(format!(".tmp-{}-{}{}", file_name, usize::MAX, EXPIRY_SUFFIX).len() <= 255);
+        }
+    }
+
+    #[test]
+    fn long_keys() {
+        let mut backend = backend("long-keys");
+        let container = "c".repeat(300);
+        let store = backend.keyvalue_open(&container).unwrap();
+        let long = "k".repeat(1024);
+        let slashes = "/".repeat(80);
+
+        store.keyvalue_set(&long, b"1").unwrap();
+        store.keyvalue_set_with_ttl(&slashes, b"2", 100).unwrap();
+        store.keyvalue_set("short", b"3").unwrap();
+        assert_eq!(store.keyvalue_get(&long).unwrap(), b"1");
+        assert_eq!(store.keyvalue_get(&slashes).unwrap(), b"2");
+        assert_eq!(store.keyvalue_get_ttl(&slashes).unwrap(), Some(100));
+        assert_eq!(store.keyvalue_increment(&long, 1).unwrap(), 2);
+        assert_eq!(
+            sorted_keys(store.as_ref()),
+            vec![slashes.clone(), long.clone(), "short".to_string()]
+        );
+
+        store.keyvalue_delete(&long).unwrap();
+        store.keyvalue_set_with_ttl(&slashes, b"2", 0).unwrap();
+        assert_eq!(sorted_keys(store.as_ref()), vec!["short"]);
+        assert!(store.keyvalue_get(&slashes).is_err());
+        // only the file of the short key is left
+        let root = backend.root.join(file_name(&container));
+        assert_eq!(fs::read_dir(root).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&backend.root);
+    }
+}
@@ -0,0 +1,598 @@
+use anyhow::Result;
+use log::debug;
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::{
+    increment_counter, keyvalue::KeyvalueError, round_to_secs, KeyvalueBackend, KeyvalueStore,
+    Watcher,
+};
+use crate::{keyvalue_handler::ChangeKind, settings::Settings};
+
+/// A value, together with the moment it expires
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.map_or(false, |t| t <= now)
+    }
+}
+
+#[derive(Default)]
+struct ContainerData {
+    entries: RwLock<HashMap<String, Entry>>,
+    watchers: RwLock<Vec<Watcher>>,
+}
+
+type Container = Arc<ContainerData>;
+
+pub struct MemoryDriver {
+    container_name: String,
+    container: Container,
+}
+
+impl fmt::Debug for MemoryDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryDriver")
+            .field("collection", &self.container_name)
+            .finish()
+    }
+}
+
+impl MemoryDriver {
+    fn new(container_name: &str, container: Container) -> Self {
+        Self {
+            container_name: container_name.to_owned(),
+            container,
+        }
+    }
+
+    /// Run `f` against the entry of the given key, if the key exists.
+    ///
+    /// Expired keys are removed lazily, the first time they are accessed.
+    fn with_entry<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&Entry, Instant) -> T,
+    ) -> Result<T, KeyvalueError> {
+        let now = Instant::now();
+        {
+            let entries = self.container.entries.read();
+            match entries.get(key) {
+                Some(entry) if !entry.is_expired(now) => return Ok(f(entry, now)),
+                Some(_) => {}
+                None => return Err(KeyvalueError::KeyNotFound(key.to_owned())),
+            }
+        }
+
+        let mut entries = self.container.entries.write();
+        if entries.get(key).map_or(false, |e| e.is_expired(now)) {
+            entries.remove(key);
+            drop(entries);
+            self.notify(key, ChangeKind::Delete);
+        }
+        Err(KeyvalueError::KeyNotFound(key.to_owned()))
+    }
+
+    fn insert(&self, key: &str, value: &[u8], expires_at: Option<Instant>) {
+        self.container.entries.write().insert(
+            key.to_owned(),
+            Entry {
+                value: value.to_vec(),
+                expires_at,
+            },
+        );
+        self.notify(key, ChangeKind::Set);
+    }
+
+    /// Let the watchers of the container know that a key has changed
+    fn notify(&self, key: &str, kind: ChangeKind) {
+        for watcher in self.container.watchers.read().iter() {
+            watcher.notify(key, kind);
+        }
+    }
+}
+
+impl KeyvalueStore for MemoryDriver {
+    /// get the payload for a given key
+    fn keyvalue_get(&self, key: &str) -> Result<Vec<u8>, KeyvalueError> {
+        debug!(key = key, container_name = self.container_name.as_str(); "memory get key");
+        self.with_entry(key, |entry, _| entry.value.clone())
+    }
+
+    /// set the payload for a given key
+    fn keyvalue_set(&self, key: &str, value: &[u8]) -> Result<(), KeyvalueError> {
+        debug!("memory set key");
+        self.insert(key, value, None);
+        Ok(())
+    }
+
+    /// set the payload for a given key, the key is deleted after ttl seconds
+    fn keyvalue_set_with_ttl(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: u64,
+    ) -> Result<(), KeyvalueError> {
+        debug!("memory set key with ttl");
+        let expires_at = Instant::now()
+            .checked_add(Duration::from_secs(ttl))
+            .ok_or_else(|| KeyvalueError::InvalidValue(format!("ttl too big: {}", ttl)))?;
+        self.insert(key, value, Some(expires_at));
+        Ok(())
+    }
+
+    /// get the seconds left before a given key expires, none if it never expires
+    fn keyvalue_get_ttl(&self, key: &str) -> Result<Option<u64>, KeyvalueError> {
+        debug!("memory get ttl");
+        self.with_entry(key, |entry, now| {
+            entry
+                .expires_at
+                .map(|t| round_to_secs(t.saturating_duration_since(now)))
+        })
+    }
+
+    /// atomically replace the payload for a given key, only if it matches the expected one
+    fn keyvalue_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, KeyvalueError> {
+        debug!("memory compare and swap");
+        let now = Instant::now();
+        let mut entries = self.container.entries.write();
+        let current = entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.value.as_slice());
+        if current != expected {
+            return Ok(false);
+        }
+        // like set, this removes the expiry of the key
+        entries.insert(
+            key.to_owned(),
+            Entry {
+                value: new.to_vec(),
+                expires_at: None,
+            },
+        );
+        drop(entries);
+        self.notify(key, ChangeKind::Set);
+        Ok(true)
+    }
+
+    /// atomically add delta to the integer stored at a given key, returning the result
+    fn keyvalue_increment(&self, key: &str, delta: i64) -> Result<i64, KeyvalueError> {
+        debug!("memory increment");
+        let now = Instant::now();
+        let mut entries = self.container.entries.write();
+        let entry = entries.get_mut(key).filter(|entry| !entry.is_expired(now));
+        let counter = increment_counter(entry.as_ref().map(|e| e.value.as_slice()), delta)?;
+        let value = counter.to_string().into_bytes();
+        match entry {
+            // like Redis, the expiry of the key is kept
+            Some(entry) => entry.value = value,
+            None => {
+                entries.insert(
+                    key.to_owned(),
+                    Entry {
+                        value,
+                        expires_at: None,
+                    },
+                );
+            }
+        }
+        drop(entries);
+        self.notify(key, ChangeKind::Set);
+        Ok(counter)
+    }
+
+    /// get the payloads for the given keys, none for the keys that don't exist
+    fn keyvalue_get_many(&self, keys: &[&str]) -> Result<Vec<Option<Vec<u8>>>, KeyvalueError> {
+        debug!("memory get many keys");
+        let now = Instant::now();
+        let entries = self.container.entries.read();
+        Ok(keys
+            .iter()
+            .map(|key| {
+                entries
+                    .get(*key)
+                    .filter(|entry| !entry.is_expired(now))
+                    .map(|entry| entry.value.clone())
+            })
+            .collect())
+    }
+
+    /// set the payloads for the given keys
+    fn keyvalue_set_many(&self, key_values: &[(&str, &[u8])]) -> Result<(), KeyvalueError> {
+        debug!("memory set many keys");
+        let mut entries = self.container.entries.write();
+        for (key, value) in key_values {
+            entries.insert(
+                key.to_string(),
+                Entry {
+                    value: value.to_vec(),
+                    expires_at: None,
+                },
+            );
+        }
+        drop(entries);
+        for (key, _) in key_values {
+            self.notify(key, ChangeKind::Set);
+        }
+        Ok(())
+    }
+
+    /// delete the payloads for the given keys
+    fn keyvalue_delete_many(&self, keys: &[&str]) -> Result<(), KeyvalueError> {
+        debug!("memory delete many keys");
+        let mut entries = self.container.entries.write();
+        let deleted: Vec<&str> = keys
+            .iter()
+            .filter(|key| entries.remove(**key).is_some())
+            .copied()
+            .collect();
+        drop(entries);
+        for key in deleted {
+            self.notify(key, ChangeKind::Delete);
+        }
+        Ok(())
+    }
+
+    /// list the keys in the store
+    fn keyvalue_keys(&self) -> Result<Vec<String>, KeyvalueError> {
+        debug!("memory keys");
+        let now = Instant::now();
+        let mut entries = self.container.entries.write();
+        let mut expired = vec![];
+        entries.retain(|key, entry| {
+            if entry.is_expired(now) {
+                expired.push(key.clone());
+            }
+            !entry.is_expired(now)
+        });
+        let keys = entries.keys().cloned().collect();
+        drop(entries);
+        for key in expired {
+            self.notify(&key, ChangeKind::Delete);
+        }
+        Ok(keys)
+    }
+
+    /// delete the payload for a given key
+    fn keyvalue_delete(&self, key: &str) -> Result<(), KeyvalueError> {
+        debug!("memory delete key");
+        if self.container.entries.write().remove(key).is_some() {
+            self.notify(key, ChangeKind::Delete);
+        }
+        Ok(())
+    }
+
+    /// notify the watcher each time a key starting with its prefix changes
+    fn keyvalue_watch(&self, watcher: Watcher) -> Result<(), KeyvalueError> {
+        debug!("memory watch");
+        self.container.watchers.write().push(watcher);
+        Ok(())
+    }
+}
+
+/// Keeps all the containers inside of the memory of the unikernel.
+/// The data is lost when the unikernel is stopped.
+#[derive(Default)]
+pub struct MemoryImplementor {
+    containers: HashMap<String, Container>,
+}
+
+impl KeyvalueBackend for MemoryImplementor {
+    fn keyvalue_open(&mut self, name: &str) -> Result<Box<dyn KeyvalueStore>, KeyvalueError> {
+        let container = self.containers.entry(name.to_owned()).or_default();
+        Ok(Box::new(MemoryDriver::new(name, container.clone())))
+    }
+}
+
+pub fn build_backend(
+    _location: Option<&str>,
+    _settings: &Settings,
+) -> Result<Box<dyn KeyvalueBackend>> {
+    Ok(Box::new(MemoryImplementor::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_messages::OperationRequest;
+    use crossbeam_channel::Receiver;
+
+    fn open(name: &str) -> Box<dyn KeyvalueStore> {
+        MemoryImplementor::default().keyvalue_open(name).unwrap()
+    }
+
+    fn watch(store: &dyn KeyvalueStore, prefix: &str) -> Receiver<OperationRequest> {
+        let (tx, rx) = crossbeam_channel::bounded(100);
+        store
+            .keyvalue_watch(Watcher {
+                prefix: prefix.to_string(),
+                handler_name: "on-change".to_string(),
+                tx,
+            })
+            .unwrap();
+        rx
+    }
+
+    fn changes(rx: &Receiver<OperationRequest>) -> Vec<(String, ChangeKind)> {
+        rx.try_iter()
+            .map(|req| match req {
+                OperationRequest::KeyvalueChanged {
+                    handler_name,
+                    key,
+                    kind,
+                } => {
+                    assert_eq!(handler_name, "on-change");
+                    (key, kind)
+                }
+                other => panic!("unexpected request {:?}", other),
+            })
+            .collect()
+    }
+
+    fn sorted_keys(store: &dyn KeyvalueStore) -> Vec<String> {
+        let mut keys = store.keyvalue_keys().unwrap();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn get_set_delete() {
+        let store = open("test");
+        assert!(matches!(
+            store.keyvalue_get("a"),
+            Err(KeyvalueError::KeyNotFound(_))
+        ));
+
+        store.keyvalue_set("a", b"1").unwrap();
+        store.keyvalue_set("b", b"").unwrap();
+        assert_eq!(store.keyvalue_get("a").unwrap(), b"1");
+        assert_eq!(store.keyvalue_get("b").unwrap(), b"");
+        assert_eq!(sorted_keys(store.as_ref()), vec!["a", "b"]);
+
+        store.keyvalue_set("a", b"2").unwrap();
+        assert_eq!(store.keyvalue_get("a").unwrap(), b"2");
+
+        store.keyvalue_delete("a").unwrap();
+        // deleting a missing key is not an error
+        store.keyvalue_delete("a").unwrap();
+        assert!(matches!(
+            store.keyvalue_get("a"),
+            Err(KeyvalueError::KeyNotFound(_))
+        ));
+        assert_eq!(sorted_keys(store.as_ref()), vec!["b"]);
+    }
+
+    #[test]
+    fn containers_are_shared_by_name() {
+        let mut backend = MemoryImplementor::default();
+        let first = backend.keyvalue_open("shared").unwrap();
+        let second = backend.keyvalue_open("shared").unwrap();
+        let other = backend.keyvalue_open("other").unwrap();
+
+        first.keyvalue_set("a", b"1").unwrap();
+        assert_eq!(second.keyvalue_get("a").unwrap(), b"1");
+        assert!(other.keyvalue_keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn expiry() {
+        let store = open("test");
+        store.keyvalue_set_with_ttl("live", b"1", 100).unwrap();
+        store.keyvalue_set_with_ttl("expired", b"1", 0).unwrap();
+        store.keyvalue_set("forever", b"1").unwrap();
+
+        assert_eq!(store.keyvalue_get_ttl("live").unwrap(), Some(100));
+        assert_eq!(store.keyvalue_get_ttl("forever").unwrap(), None);
+        assert!(matches!(
+            store.keyvalue_get("expired"),
+            Err(KeyvalueError::KeyNotFound(_))
+        ));
+        assert!(matches!(
+            store.keyvalue_get_ttl("expired"),
+            Err(KeyvalueError::KeyNotFound(_))
+        ));
+        assert_eq!(
+            store.keyvalue_get_many(&["live", "expired"]).unwrap(),
+            vec![Some(b"1".to_vec()), None]
+        );
+
+        store.keyvalue_set_with_ttl("expired", b"1", 0).unwrap();
+        assert_eq!(sorted_keys(store.as_ref()), vec!["forever", "live"]);
+
+        // set removes the expiry
+        store.keyvalue_set("live", b"2").unwrap();
+        assert_eq!(store.keyvalue_get_ttl("live").unwrap(), None);
+
+        assert!(matches!(
+            store.keyvalue_set_with_ttl("a", b"1", u64::MAX),
+            Err(KeyvalueError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn compare_and_swap() {
+        let store = open("test");
+        assert!(!store
+            .keyvalue_compare_and_swap("a", Some(b"1"), b"2")
+            .unwrap());
+        assert!(store.keyvalue_compare_and_swap("a", None, b"1").unwrap());
+        assert!(!store.keyvalue_compare_and_swap("a", None, b"2").unwrap());
+        assert!(!store
+            .keyvalue_compare_and_swap("a", Some(b"2"), b"3")
+            .unwrap());
+        assert!(store
+            .keyvalue_compare_and_swap("a", Some(b"1"), b"2")
+            .unwrap());
+        assert_eq!(store.keyvalue_get("a").unwrap(), b"2");
+
+        // expired keys are missing, and the swap removes the expiry
+        store.keyvalue_set_with_ttl("b", b"1", 0).unwrap();
+        assert!(!store
+            .keyvalue_compare_and_swap("b", Some(b"1"), b"2")
+            .unwrap());
+        assert!(store.keyvalue_compare_and_swap("b", None, b"2").unwrap());
+        store.keyvalue_set_with_ttl("c", b"1", 100).unwrap();
+        assert!(store
+            .keyvalue_compare_and_swap("c", Some(b"1"), b"2")
+            .unwrap());
+        assert_eq!(store.keyvalue_get_ttl("c").unwrap(), None);
+    }
+
+    #[test]
+    fn increment() {
+        let store = open("test");
+        assert_eq!(store.keyvalue_increment("n", 5).unwrap(), 5);
+        assert_eq!(store.keyvalue_increment("n", -7).unwrap(), -2);
+        assert_eq!(store.keyvalue_get("n").unwrap(), b"-2");
+
+        store.keyvalue_set("s", b"abc").unwrap();
+        assert!(matches!(
+            store.keyvalue_increment("s", 1),
+            Err(KeyvalueError::InvalidValue(_))
+        ));
+        store
+            .keyvalue_set("max", i64::MAX.to_string().as_bytes())
+            .unwrap();
+        assert!(matches!(
+            store.keyvalue_increment("max", 1),
+            Err(KeyvalueError::InvalidValue(_))
+        ));
+
+        // the expiry is kept, like Redis does
+        store.keyvalue_set_with_ttl("t", b"1", 100).unwrap();
+        assert_eq!(store.keyvalue_increment("t", 1).unwrap(), 2);
+        assert_eq!(store.keyvalue_get_ttl("t").unwrap(), Some(100));
+
+        // expired counters start again from zero
+        store.keyvalue_set_with_ttl("e", b"10", 0).unwrap();
+        assert_eq!(store.keyvalue_increment("e", 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn many() {
+        let store = open("test");
+        store
+            .keyvalue_set_many(&[("a", b"1".as_slice()), ("b", b"2".as_slice())])
+            .unwrap();
+        assert_eq!(
+            store.keyvalue_get_many(&["a", "missing", "b"]).unwrap(),
+            vec![Some(b"1".to_vec()), None, Some(b"2".to_vec())]
+        );
+        store.keyvalue_delete_many(&["a", "missing"]).unwrap();
+        assert_eq!(sorted_keys(store.as_ref()), vec!["b"]);
+    }
+
+    #[test]
+    fn keys_with_prefix_and_pages() {
+        let store = open("test");
+        for key in ["user-1", "user-2", "user-3", "order-1", "order-2"] {
+            store.keyvalue_set(key, b"").unwrap();
+        }
+
+        let mut keys = store.keyvalue_keys_with_prefix("user-").unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["user-1", "user-2", "user-3"]);
+
+        let page = store.keyvalue_keys_page(None, 2).unwrap();
+        assert_eq!(page.keys, vec!["order-1", "order-2"]);
+        assert_eq!(page.next_cursor.as_deref(), Some("order-2"));
+        let page = store
+            .keyvalue_keys_page(page.next_cursor.as_deref(), 2)
+            .unwrap();
+        assert_eq!(page.keys, vec!["user-1", "user-2"]);
+        let page = store
+            .keyvalue_keys_page(page.next_cursor.as_deref(), 2)
+            .unwrap();
+        assert_eq!(page.keys, vec!["user-3"]);
+        assert_eq!(page.next_cursor, None);
+
+        // a page filled exactly by the last keys has no next page
+        let page = store.keyvalue_keys_page(Some("order-2"), 3).unwrap();
+        assert_eq!(page.keys, vec!["user-1", "user-2", "user-3"]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn watch_changes() {
+        let store = open("test");
+        let rx = watch(store.as_ref(), "user-");
+
+        store.keyvalue_set("user-1", b"1").unwrap();
+        store.keyvalue_set("order-1", b"1").unwrap();
+        store.keyvalue_increment("user-2", 1).unwrap();
+        assert!(store
+            .keyvalue_compare_and_swap("user-3", None, b"1")
+            .unwrap());
+        // failed swaps don't change the key
+        assert!(!store
+            .keyvalue_compare_and_swap("user-3", None, b"2")
+            .unwrap());
+        store.keyvalue_delete("user-1").unwrap();
+        // deleting a missing key doesn't change it
+        store.keyvalue_delete("user-1").unwrap();
+        store
+            .keyvalue_set_many(&[("user-4", b"1".as_slice()), ("order-2", b"1".as_slice())])
+            .unwrap();
+        store.keyvalue_delete_many(&["user-4", "user-5"]).unwrap();
+
+        assert_eq!(
+            changes(&rx),
+            vec![
+                ("user-1".to_string(), ChangeKind::Set),
+                ("user-2".to_string(), ChangeKind::Set),
+                ("user-3".to_string(), ChangeKind::Set),
+                ("user-1".to_string(), ChangeKind::Delete),
+                ("user-4".to_string(), ChangeKind::Set),
+                ("user-4".to_string(), ChangeKind::Delete),
+            ]
+        );
+    }
+
+    #[test]
+    fn watch_expiry() {
+        let store = open("test");
+        store.keyvalue_set_with_ttl("a", b"1", 0).unwrap();
+        store.keyvalue_set_with_ttl("b", b"1", 0).unwrap();
+        let rx = watch(store.as_ref(), "");
+
+        // expired keys are deleted the first time they are accessed
+        assert!(store.keyvalue_get("a").is_err());
+        assert!(store.keyvalue_get("a").is_err());
+        store.keyvalue_keys().unwrap();
+        assert_eq!(
+            changes(&rx),
+            vec![
+                ("a".to_string(), ChangeKind::Delete),
+                ("b".to_string(), ChangeKind::Delete),
+            ]
+        );
+    }
+
+    #[test]
+    fn watchers_see_the_changes_of_all_the_stores_of_a_container() {
+        let mut backend = MemoryImplementor::default();
+        let first = backend.keyvalue_open("shared").unwrap();
+        let second = backend.keyvalue_open("shared").unwrap();
+        let other = backend.keyvalue_open("other").unwrap();
+        let rx = watch(first.as_ref(), "");
+
+        second.keyvalue_set("a", b"1").unwrap();
+        other.keyvalue_set("b", b"1").unwrap();
+        assert_eq!(changes(&rx), vec![("a".to_string(), ChangeKind::Set)]);
+    }
+}
@@ -1,3 +1,812 @@
 wit_bindgen_wasmi::export!({paths: ["wit/keyvalue.wit"]});
 
+pub mod file;
+pub mod memory;
+pub mod read_only;
 pub mod redis;
+
+use crate::{channel_messages::OperationRequest, keyvalue_handler::ChangeKind, settings::Settings};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use keyvalue::{KeysPage, Keyvalue, KeyvalueError, KeyvalueTables};
+use log::{info, warn};
+use read_only::ReadOnlyStore;
+use std::{collections::HashMap, fmt, time::Duration};
+
+/// A key-value store opened by one of the backends
+pub trait KeyvalueStore: fmt::Debug + Send + Sync {
+    /// get the payload for a given key
+    fn keyvalue_get(&self, key: &str) -> Result<Vec<u8>, KeyvalueError>;
+
+    /// set the payload for a given key
+    fn keyvalue_set(&self, key: &str, value: &[u8]) -> Result<(), KeyvalueError>;
+
+    /// set the payload for a given key, the key is deleted after ttl seconds
+    fn keyvalue_set_with_ttl(&self, key: &str, value: &[u8], ttl: u64)
+        -> Result<(), KeyvalueError>;
+
+    /// get the seconds left before a given key expires, none if it never expires
+    fn keyvalue_get_ttl(&self, key: &str) -> Result<Option<u64>, KeyvalueError>;
+
+    /// atomically replace the payload for a given key, only if it matches the expected one
+    fn keyvalue_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, KeyvalueError>;
+
+    /// atomically add delta to the integer stored at a given key, returning the result
+    fn keyvalue_increment(&self, key: &str, delta: i64) -> Result<i64, KeyvalueError>;
+
+    /// get the payloads for the given keys, none for the keys that don't exist
+    ///
+    /// By default the keys are fetched one at a time.
+    fn keyvalue_get_many(&self, keys: &[&str]) -> Result<Vec<Option<Vec<u8>>>, KeyvalueError> {
+        keys.iter()
+            .map(|key| match self.keyvalue_get(key) {
+                Ok(value) => Ok(Some(value)),
+                Err(KeyvalueError::KeyNotFound(_)) => Ok(None),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+
+    /// set the payloads for the given keys
+    ///
+    /// By default the keys are set one at a time.
+    fn keyvalue_set_many(&self, key_values: &[(&str, &[u8])]) -> Result<(), KeyvalueError> {
+        for (key, value) in key_values {
+            self.keyvalue_set(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// delete the payloads for the given keys
+    ///
+    /// By default the keys are deleted one at a time.
+    fn keyvalue_delete_many(&self, keys: &[&str]) -> Result<(), KeyvalueError> {
+        for key in keys {
+            self.keyvalue_delete(key)?;
+        }
+        Ok(())
+    }
+
+    /// list the keys in the store
+    fn keyvalue_keys(&self) -> Result<Vec<String>, KeyvalueError>;
+
+    /// list the keys in the store starting with the given prefix
+    ///
+    /// By default all the keys are listed, then filtered.
+    fn keyvalue_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, KeyvalueError> {
+        let mut keys = self.keyvalue_keys()?;
+        keys.retain(|key| key.starts_with(prefix));
+        Ok(keys)
+    }
+
+    /// list at most limit keys of the store, starting from the given cursor
+    ///
+    /// By default the keys are sorted, and the cursor is the last key of
+    /// the previous page.
+    fn keyvalue_keys_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<KeysPage, KeyvalueError> {
+        let mut keys = self.keyvalue_keys()?;
+        if let Some(cursor) = cursor {
+            keys.retain(|key| key.as_str() > cursor);
+        }
+        keys.sort_unstable();
+
+        let next_cursor = if keys.len() > limit {
+            keys.truncate(limit);
+            keys.last().cloned()
+        } else {
+            None
+        };
+        Ok(KeysPage { keys, next_cursor })
+    }
+
+    /// delete the payload for a given key
+    fn keyvalue_delete(&self, key: &str) -> Result<(), KeyvalueError>;
+
+    /// notify the watcher each time a key starting with its prefix changes
+    ///
+    /// By default watching keys is not supported.
+    fn keyvalue_watch(&self, _watcher: Watcher) -> Result<(), KeyvalueError> {
+        Err(KeyvalueError::UnexpectedError(
+            "watching keys is not supported by this keyvalue backend".to_string(),
+        ))
+    }
+}
+
+/// A handler of the WebAssembly module, invoked each time a key starting with
+/// the prefix changes. The handler is run by the main event loop.
+#[derive(Clone)]
+pub struct Watcher {
+    prefix: String,
+    handler_name: String,
+    tx: Sender<OperationRequest>,
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watcher")
+            .field("prefix", &self.prefix)
+            .field("handler_name", &self.handler_name)
+            .finish()
+    }
+}
+
+impl Watcher {
+    /// Queue the invocation of the handler, if the key is watched. The change
+    /// is dropped when too many events are waiting to be handled, which
+    /// prevents the backends from blocking on the event loop.
+    pub fn notify(&self, key: &str, kind: ChangeKind) {
+        if !key.starts_with(&self.prefix) {
+            return;
+        }
+        let req = OperationRequest::KeyvalueChanged {
+            handler_name: self.handler_name.clone(),
+            key: key.to_string(),
+            kind,
+        };
+        if let Err(e) = self.tx.try_send(req) {
+            warn!(
+                "dropping the change of key '{}' watched by '{}': {}",
+                key, self.handler_name, e
+            );
+        }
+    }
+}
+
+/// Convert the time left before a key expires into seconds, rounding
+/// to the nearest second like Redis does
+fn round_to_secs(d: Duration) -> u64 {
+    ((d.as_millis() + 500) / 1000) as u64
+}
+
+/// Add delta to a counter, stored as a decimal number like Redis does.
+/// A missing counter starts from zero.
+fn increment_counter(value: Option<&[u8]>, delta: i64) -> Result<i64, KeyvalueError> {
+    let current = match value {
+        Some(value) => std::str::from_utf8(value)
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .ok_or_else(|| {
+                KeyvalueError::InvalidValue("the value is not an integer".to_string())
+            })?,
+        None => 0,
+    };
+    current
+        .checked_add(delta)
+        .ok_or_else(|| KeyvalueError::InvalidValue("increment would overflow".to_string()))
+}
+
+/// An implementation of the keyvalue interface
+pub trait KeyvalueBackend: Send + Sync {
+    /// open a key-value store
+    fn keyvalue_open(&mut self, name: &str) -> Result<Box<dyn KeyvalueStore>, KeyvalueError>;
+}
+
+/// Builds a keyvalue backend, using the given settings. The location, when
+/// provided, takes precedence over the one found inside of the settings.
+type BackendBuilder = fn(Option<&str>, &Settings) -> Result<Box<dyn KeyvalueBackend>>;
+
+/// The keyvalue backends that can be chosen at startup, by name
+const BACKENDS: &[(&str, BackendBuilder)] = &[
+    ("redis", redis::build_backend),
+    ("memory", memory::build_backend),
+    ("file", file::build_backend),
+];
+
+/// The names of all the available keyvalue backends
+pub fn backend_names() -> Vec<&'static str> {
+    BACKENDS.iter().map(|(name, _)| *name).collect()
+}
+
+fn build_backend(
+    name: &str,
+    location: Option<&str>,
+    settings: &Settings,
+) -> Result<Box<dyn KeyvalueBackend>> {
+    let (_, builder) = BACKENDS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .ok_or_else(|| anyhow!("Unknown keyvalue backend: {}", name))?;
+    builder(location, settings)
+}
+
+/// The characters Redis uses to separate the container from the key and to
+/// delimit the hash tags, which are never allowed in the name of a container
+pub const CONTAINER_FORBIDDEN_CHARS: [char; 3] = [':', '{', '}'];
+
+/// Restrictions on the keys and values written by the WebAssembly module,
+/// enforced before reaching the backends
+struct Limits {
+    max_key_length: usize,
+    forbidden_key_chars: Vec<char>,
+    max_value_size: usize,
+}
+
+impl Limits {
+    fn new(settings: &Settings) -> Self {
+        Self {
+            max_key_length: settings.keyvalue_max_key_length,
+            forbidden_key_chars: settings.keyvalue_forbidden_key_chars.chars().collect(),
+            max_value_size: settings.keyvalue_max_value_size,
+        }
+    }
+
+    /// Check a prefix of keys, which is allowed to be empty
+    fn check_prefix(&self, prefix: &str) -> Result<(), KeyvalueError> {
+        if prefix.len() > self.max_key_length {
+            return Err(KeyvalueError::InvalidKey(format!(
+                "key is {} bytes long, the maximum is {}",
+                prefix.len(),
+                self.max_key_length
+            )));
+        }
+        if let Some(c) = prefix
+            .chars()
+            .find(|c| self.forbidden_key_chars.contains(c))
+        {
+            return Err(KeyvalueError::InvalidKey(format!(
+                "key {:?} contains the forbidden character {:?}",
+                prefix, c
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the name of a container. The backends build the names of the
+    /// keys out of it, hence it must not let a container overlap another one.
+    fn check_container(&self, name: &str) -> Result<(), KeyvalueError> {
+        if name.is_empty() {
+            return Err(KeyvalueError::InvalidKey(
+                "keyvalue store name is empty".to_string(),
+            ));
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| CONTAINER_FORBIDDEN_CHARS.contains(c) || self.forbidden_key_chars.contains(c))
+        {
+            return Err(KeyvalueError::InvalidKey(format!(
+                "keyvalue store name {:?} contains the forbidden character {:?}",
+                name, c
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_key(&self, key: &str) -> Result<(), KeyvalueError> {
+        if key.is_empty() {
+            return Err(KeyvalueError::InvalidKey("key is empty".to_string()));
+        }
+        self.check_prefix(key)
+    }
+
+    fn check_keys(&self, keys: &[&str]) -> Result<(), KeyvalueError> {
+        keys.iter().try_for_each(|key| self.check_key(key))
+    }
+
+    fn check_value(&self, value: &[u8]) -> Result<(), KeyvalueError> {
+        if value.len() > self.max_value_size {
+            return Err(KeyvalueError::InvalidValue(format!(
+                "value is {} bytes long, the maximum is {}",
+                value.len(),
+                self.max_value_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How the WebAssembly module can use a container
+#[derive(Clone, Copy, PartialEq)]
+enum Access {
+    ReadWrite,
+    ReadOnly,
+}
+
+/// Exposes the keyvalue backends chosen by the user to the WebAssembly module
+pub struct KeyvalueImplementor {
+    /// The backends of the stores that have been bound explicitly, by store name
+    stores: HashMap<String, Box<dyn KeyvalueBackend>>,
+    /// The backend used by all the other stores
+    default_backend: Option<Box<dyn KeyvalueBackend>>,
+    /// The containers the WebAssembly module is allowed to open. When not
+    /// provided, all of them can be opened
+    allowed_containers: Option<HashMap<String, Access>>,
+    limits: Limits,
+    /// Used to invoke the handlers of the watched keys
+    event_tx: Sender<OperationRequest>,
+    /// Whether some keys are being watched
+    watching: bool,
+}
+
+impl KeyvalueImplementor {
+    /// Whether the WebAssembly module is watching some keys, hence it
+    /// expects its handlers to be invoked
+    pub fn is_watching(&self) -> bool {
+        self.watching
+    }
+}
+
+impl Keyvalue for KeyvalueImplementor {
+    type Keyvalue = Box<dyn KeyvalueStore>;
+
+    fn keyvalue_open(&mut self, name: &str) -> Result<Self::Keyvalue, KeyvalueError> {
+        self.limits.check_container(name)?;
+        let access = match &self.allowed_containers {
+            Some(allowed) => *allowed.get(name).ok_or_else(|| {
+                warn!(
+                    "the WebAssembly module is not allowed to open keyvalue store '{}'",
+                    name
+                );
+                KeyvalueError::AuthenticationError(format!(
+                    "access to keyvalue store '{}' is not allowed",
+                    name
+                ))
+            })?,
+            None => Access::ReadWrite,
+        };
+
+        let backend = match self.stores.get_mut(name) {
+            Some(b) => b,
+            None => self.default_backend.as_mut().ok_or_else(|| {
+                KeyvalueError::UnexpectedError(format!(
+                    "no keyvalue store named '{}' has been configured",
+                    name
+                ))
+            })?,
+        };
+        let store = backend.keyvalue_open(name)?;
+        match access {
+            Access::ReadWrite => Ok(store),
+            Access::ReadOnly => Ok(Box::new(ReadOnlyStore::new(name, store))),
+        }
+    }
+
+    /// get the payload for a given key
+    fn keyvalue_get(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key: &str,
+    ) -> Result<Vec<u8>, KeyvalueError> {
+        self.limits.check_key(key)?;
+        self_.keyvalue_get(key)
+    }
+
+    /// set the payload for a given key
+    fn keyvalue_set(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), KeyvalueError> {
+        self.limits.check_key(key)?;
+        self.limits.check_value(value)?;
+        self_.keyvalue_set(key, value)
+    }
+
+    /// set the payload for a given key, the key is deleted after ttl seconds
+    fn keyvalue_set_with_ttl(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key: &str,
+        value: &[u8],
+        ttl: u64,
+    ) -> Result<(), KeyvalueError> {
+        if ttl == 0 {
+            return Err(KeyvalueError::InvalidValue(
+                "the ttl must be greater than zero".to_string(),
+            ));
+        }
+        self.limits.check_key(key)?;
+        self.limits.check_value(value)?;
+        self_.keyvalue_set_with_ttl(key, value, ttl)
+    }
+
+    /// get the seconds left before a given key expires, none if it never expires
+    fn keyvalue_get_ttl(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key: &str,
+    ) -> Result<Option<u64>, KeyvalueError> {
+        self.limits.check_key(key)?;
+        self_.keyvalue_get_ttl(key)
+    }
+
+    /// atomically replace the payload for a given key, only if it matches the expected one
+    fn keyvalue_compare_and_swap(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key: &str,
+        expected: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, KeyvalueError> {
+        self.limits.check_key(key)?;
+        self.limits.check_value(new)?;
+        self_.keyvalue_compare_and_swap(key, expected, new)
+    }
+
+    /// atomically add delta to the integer stored at a given key, returning the result
+    fn keyvalue_increment(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key: &str,
+        delta: i64,
+    ) -> Result<i64, KeyvalueError> {
+        self.limits.check_key(key)?;
+        self_.keyvalue_increment(key, delta)
+    }
+
+    /// get the payloads for the given keys, none for the keys that don't exist
+    fn keyvalue_get_many(
+        &mut self,
+        self_: &Self::Keyvalue,
+        keys: Vec<&str>,
+    ) -> Result<Vec<Option<Vec<u8>>>, KeyvalueError> {
+        self.limits.check_keys(&keys)?;
+        self_.keyvalue_get_many(&keys)
+    }
+
+    /// set the payloads for the given keys
+    fn keyvalue_set_many(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key_values: Vec<(&str, &[u8])>,
+    ) -> Result<(), KeyvalueError> {
+        for (key, value) in &key_values {
+            self.limits.check_key(key)?;
+            self.limits.check_value(value)?;
+        }
+        self_.keyvalue_set_many(&key_values)
+    }
+
+    /// delete the payloads for the given keys
+    fn keyvalue_delete_many(
+        &mut self,
+        self_: &Self::Keyvalue,
+        keys: Vec<&str>,
+    ) -> Result<(), KeyvalueError> {
+        self.limits.check_keys(&keys)?;
+        self_.keyvalue_delete_many(&keys)
+    }
+
+    /// list the keys in the store
+    fn keyvalue_keys(&mut self, self_: &Self::Keyvalue) -> Result<Vec<String>, KeyvalueError> {
+        self_.keyvalue_keys()
+    }
+
+    /// list the keys in the store starting with the given prefix
+    fn keyvalue_keys_with_prefix(
+        &mut self,
+        self_: &Self::Keyvalue,
+        prefix: &str,
+    ) -> Result<Vec<String>, KeyvalueError> {
+        self.limits.check_prefix(prefix)?;
+        self_.keyvalue_keys_with_prefix(prefix)
+    }
+
+    /// list at most limit keys of the store, starting from the given cursor
+    fn keyvalue_keys_page(
+        &mut self,
+        self_: &Self::Keyvalue,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeysPage, KeyvalueError> {
+        if limit == 0 {
+            return Err(KeyvalueError::InvalidValue(
+                "the limit must be greater than zero".to_string(),
+            ));
+        }
+        self_.keyvalue_keys_page(cursor, limit as usize)
+    }
+
+    /// delete the payload for a given key
+    fn keyvalue_delete(&mut self, self_: &Self::Keyvalue, key: &str) -> Result<(), KeyvalueError> {
+        self.limits.check_key(key)?;
+        self_.keyvalue_delete(key)
+    }
+
+    /// invoke the handler exported by the module each time a key starting with key-prefix changes
+    fn keyvalue_watch(
+        &mut self,
+        self_: &Self::Keyvalue,
+        key_prefix: &str,
+        handler: &str,
+    ) -> Result<(), KeyvalueError> {
+        self.limits.check_prefix(key_prefix)?;
+        if handler.is_empty() {
+            return Err(KeyvalueError::UnexpectedError(
+                "the name of the handler cannot be empty".to_string(),
+            ));
+        }
+        // like the http handlers, the functions are exported using dashes
+        let watcher = Watcher {
+            prefix: key_prefix.to_string(),
+            handler_name: handler.replace('_', "-"),
+            tx: self.event_tx.clone(),
+        };
+        self_.keyvalue_watch(watcher)?;
+        self.watching = true;
+        Ok(())
+    }
+}
+
+pub struct KeyvalueContext {
+    pub kv: KeyvalueImplementor,
+    pub table: KeyvalueTables<KeyvalueImplementor>,
+}
+
+impl KeyvalueContext {
+    pub fn new(settings: &Settings, event_tx: Sender<OperationRequest>) -> Result<Self> {
+        let mut stores = HashMap::new();
+        for binding in &settings.keyvalue_stores {
+            info!(
+                "binding keyvalue store '{}' to the {} backend",
+                binding.name, binding.backend
+            );
+            let backend = build_backend(&binding.backend, binding.location.as_deref(), settings)?;
+            stores.insert(binding.name.clone(), backend);
+        }
+
+        let default_backend = settings
+            .keyvalue_backend
+            .as_deref()
+            .map(|name| build_backend(name, None, settings))
+            .transpose()?;
+
+        let allowed_containers = if settings.keyvalue_allowed_containers.is_empty()
+            && settings.keyvalue_read_only_containers.is_empty()
+        {
+            None
+        } else {
+            let read_write = settings
+                .keyvalue_allowed_containers
+                .iter()
+                .map(|name| (name.clone(), Access::ReadWrite));
+            let read_only = settings
+                .keyvalue_read_only_containers
+                .iter()
+                .map(|name| (name.clone(), Access::ReadOnly));
+            Some(read_write.chain(read_only).collect())
+        };
+
+        Ok(Self {
+            kv: KeyvalueImplementor {
+                stores,
+                default_backend,
+                allowed_containers,
+                limits: Limits::new(settings),
+                event_tx,
+                watching: false,
+            },
+            table: KeyvalueTables::<KeyvalueImplementor>::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn implementor(
+        allowed_containers: Option<&[(&str, Access)]>,
+        forbidden_key_chars: &str,
+    ) -> KeyvalueImplementor {
+        let (event_tx, _) = crossbeam_channel::unbounded();
+        KeyvalueImplementor {
+            stores: HashMap::new(),
+            default_backend: Some(Box::new(memory::MemoryImplementor::default())),
+            allowed_containers: allowed_containers.map(|allowed| {
+                allowed
+                    .iter()
+                    .map(|(name, access)| (name.to_string(), *access))
+                    .collect()
+            }),
+            limits: Limits {
+                max_key_length: 8,
+                forbidden_key_chars: forbidden_key_chars.chars().collect(),
+                max_value_size: 4,
+            },
+            event_tx,
+            watching: false,
+        }
+    }
+
+    #[test]
+    fn container_names_cannot_overlap() {
+        let mut kv = implementor(None, ":");
+        for name in ["", "b:c", "{b}", "b}", "b{"] {
+            assert!(matches!(
+                kv.keyvalue_open(name),
+                Err(KeyvalueError::InvalidKey(_))
+            ));
+        }
+        assert!(kv.keyvalue_open("b").is_ok());
+
+        // the forbidden key characters are refused too
+        let mut kv = implementor(None, "/");
+        assert!(matches!(
+            kv.keyvalue_open("b/c"),
+            Err(KeyvalueError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn allowed_container_cannot_reach_nested_one() {
+        let allowed = [("b", Access::ReadWrite), ("b:c", Access::ReadOnly)];
+        let mut kv = implementor(Some(&allowed), ":");
+        assert!(matches!(
+            kv.keyvalue_open("b:c"),
+            Err(KeyvalueError::InvalidKey(_))
+        ));
+
+        // the keys of "b:c" would be the keys of "b" starting with "c:"
+        let store = kv.keyvalue_open("b").unwrap();
+        assert!(matches!(
+            kv.keyvalue_keys_with_prefix(&store, "c:"),
+            Err(KeyvalueError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            kv.keyvalue_set(&store, "c:d", b"x"),
+            Err(KeyvalueError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn only_allowed_containers_can_be_opened() {
+        let allowed = [("rw", Access::ReadWrite), ("ro", Access::ReadOnly)];
+        let mut kv = implementor(Some(&allowed), ":");
+        assert!(matches!(
+            kv.keyvalue_open("other"),
+            Err(KeyvalueError::AuthenticationError(_))
+        ));
+
+        let store = kv.keyvalue_open("rw").unwrap();
+        assert!(kv.keyvalue_set(&store, "a", b"1").is_ok());
+
+        let store = kv.keyvalue_open("ro").unwrap();
+        assert!(matches!(
+            kv.keyvalue_set(&store, "a", b"1"),
+            Err(KeyvalueError::AuthenticationError(_))
+        ));
+        assert!(matches!(
+            kv.keyvalue_get(&store, "a"),
+            Err(KeyvalueError::KeyNotFound(_))
+        ));
+    }
+
+    fn invalid_key<T>(result: Result<T, KeyvalueError>) -> bool {
+        matches!(result, Err(KeyvalueError::InvalidKey(_)))
+    }
+
+    fn invalid_value<T>(result: Result<T, KeyvalueError>) -> bool {
+        matches!(result, Err(KeyvalueError::InvalidValue(_)))
+    }
+
+    #[test]
+    fn key_length() {
+        let mut kv = implementor(None, ":");
+        let store = kv.keyvalue_open("test").unwrap();
+        assert!(invalid_key(kv.keyvalue_set(&store, "", b"v")));
+        assert!(invalid_key(kv.keyvalue_get(&store, "")));
+        assert!(kv.keyvalue_set(&store, "12345678", b"v").is_ok());
+        assert!(invalid_key(kv.keyvalue_set(&store, "123456789", b"v")));
+        assert!(invalid_key(kv.keyvalue_get(&store, "123456789")));
+        assert!(invalid_key(kv.keyvalue_delete(&store, "123456789")));
+
+        // unlike the keys, the prefixes can be empty
+        assert!(kv.keyvalue_keys_with_prefix(&store, "").is_ok());
+        assert!(kv.keyvalue_keys_with_prefix(&store, "12345678").is_ok());
+        assert!(invalid_key(
+            kv.keyvalue_keys_with_prefix(&store, "123456789")
+        ));
+    }
+
+    #[test]
+    fn forbidden_key_chars() {
+        let mut kv = implementor(None, ":/*");
+        let store = kv.keyvalue_open("test").unwrap();
+        for key in ["a:b", "/a", "a*"] {
+            assert!(invalid_key(kv.keyvalue_set(&store, key, b"v")));
+            assert!(invalid_key(kv.keyvalue_increment(&store, key, 1)));
+            assert!(invalid_key(kv.keyvalue_keys_with_prefix(&store, key)));
+            assert!(invalid_key(kv.keyvalue_watch(&store, key, "on-change")));
+        }
+        assert!(kv.keyvalue_set(&store, "a.b-c_d", b"v").is_ok());
+    }
+
+    #[test]
+    fn value_size() {
+        let mut kv = implementor(None, ":");
+        let store = kv.keyvalue_open("test").unwrap();
+        assert!(kv.keyvalue_set(&store, "a", b"1234").is_ok());
+        assert!(invalid_value(kv.keyvalue_set(&store, "a", b"12345")));
+        assert!(invalid_value(
+            kv.keyvalue_set_with_ttl(&store, "a", b"12345", 10)
+        ));
+        assert!(invalid_value(kv.keyvalue_compare_and_swap(
+            &store,
+            "a",
+            Some(b"1234"),
+            b"12345"
+        )));
+        assert_eq!(kv.keyvalue_get(&store, "a").unwrap(), b"1234");
+    }
+
+    #[test]
+    fn batches_are_checked_as_a_whole() {
+        let mut kv = implementor(None, ":");
+        let store = kv.keyvalue_open("test").unwrap();
+        kv.keyvalue_set(&store, "a", b"1").unwrap();
+
+        assert!(invalid_value(
+            kv.keyvalue_set_many(&store, vec![("b", b"2"), ("c", b"12345")])
+        ));
+        assert!(invalid_key(
+            kv.keyvalue_set_many(&store, vec![("b", b"2"), ("c:d", b"3")])
+        ));
+        assert_eq!(kv.keyvalue_keys(&store).unwrap(), vec!["a"]);
+
+        assert!(invalid_key(kv.keyvalue_get_many(&store, vec!["a", ""])));
+        assert!(invalid_key(
+            kv.keyvalue_delete_many(&store, vec!["a", "b:c"])
+        ));
+        assert_eq!(kv.keyvalue_get(&store, "a").unwrap(), b"1");
+    }
+
+    #[test]
+    fn ttl_and_limit_must_be_positive() {
+        let mut kv = implementor(None, ":");
+        let store = kv.keyvalue_open("test").unwrap();
+        assert!(invalid_value(
+            kv.keyvalue_set_with_ttl(&store, "a", b"1", 0)
+        ));
+        assert!(matches!(
+            kv.keyvalue_get(&store, "a"),
+            Err(KeyvalueError::KeyNotFound(_))
+        ));
+        assert!(kv.keyvalue_set_with_ttl(&store, "a", b"1", 1).is_ok());
+
+        assert!(invalid_value(kv.keyvalue_keys_page(&store, None, 0)));
+        assert!(kv.keyvalue_keys_page(&store, None, 1).is_ok());
+    }
+
+    #[test]
+    fn default_keys_page() {
+        let store = memory::MemoryImplementor::default()
+            .keyvalue_open("test")
+            .unwrap();
+        for key in ["d", "b", "a", "c", "e"] {
+            store.keyvalue_set(key, b"").unwrap();
+        }
+
+        let page = store.keyvalue_keys_page(None, 2).unwrap();
+        assert_eq!(page.keys, vec!["a", "b"]);
+        assert_eq!(page.next_cursor.as_deref(), Some("b"));
+
+        // the cursor doesn't need to be an existing key
+        store.keyvalue_delete("b").unwrap();
+        let page = store.keyvalue_keys_page(Some("b"), 2).unwrap();
+        assert_eq!(page.keys, vec!["c", "d"]);
+        assert_eq!(page.next_cursor.as_deref(), Some("d"));
+
+        let page = store.keyvalue_keys_page(Some("d"), 2).unwrap();
+        assert_eq!(page.keys, vec!["e"]);
+        assert_eq!(page.next_cursor, None);
+
+        let page = store.keyvalue_keys_page(None, 10).unwrap();
+        assert_eq!(page.keys, vec!["a", "c", "d", "e"]);
+        assert_eq!(page.next_cursor, None);
+
+        let page = store.keyvalue_keys_page(Some("z"), 10).unwrap();
+        assert!(page.keys.is_empty());
+        assert_eq!(page.next_cursor, None);
+    }
+}
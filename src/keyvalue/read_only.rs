@@ -0,0 +1,210 @@
+use log::debug;
+
+use super::{
+    keyvalue::{KeysPage, KeyvalueError},
+    KeyvalueStore, Watcher,
+};
+
+/// Wraps a store the WebAssembly module is only allowed to read from.
+/// All the writes fail with an authentication error.
+#[derive(Debug)]
+pub struct ReadOnlyStore {
+    container_name: String,
+    store: Box<dyn KeyvalueStore>,
+}
+
+impl ReadOnlyStore {
+    pub fn new(container_name: &str, store: Box<dyn KeyvalueStore>) -> Self {
+        Self {
+            container_name: container_name.to_owned(),
+            store,
+        }
+    }
+
+    fn denied<T>(&self) -> Result<T, KeyvalueError> {
+        debug!(
+            container_name = self.container_name.as_str();
+            "write to read-only container denied"
+        );
+        Err(KeyvalueError::AuthenticationError(format!(
+            "keyvalue store '{}' is read-only",
+            self.container_name
+        )))
+    }
+}
+
+impl KeyvalueStore for ReadOnlyStore {
+    /// get the payload for a given key
+    fn keyvalue_get(&self, key: &str) -> Result<Vec<u8>, KeyvalueError> {
+        self.store.keyvalue_get(key)
+    }
+
+    /// set the payload for a given key
+    fn keyvalue_set(&self, _key: &str, _value: &[u8]) -> Result<(), KeyvalueError> {
+        self.denied()
+    }
+
+    /// set the payload for a given key, the key is deleted after ttl seconds
+    fn keyvalue_set_with_ttl(
+        &self,
+        _key: &str,
+        _value: &[u8],
+        _ttl: u64,
+    ) -> Result<(), KeyvalueError> {
+        self.denied()
+    }
+
+    /// get the seconds left before a given key expires, none if it never expires
+    fn keyvalue_get_ttl(&self, key: &str) -> Result<Option<u64>, KeyvalueError> {
+        self.store.keyvalue_get_ttl(key)
+    }
+
+    /// atomically replace the payload for a given key, only if it matches the expected one
+    fn keyvalue_compare_and_swap(
+        &self,
+        _key: &str,
+        _expected: Option<&[u8]>,
+        _new: &[u8],
+    ) -> Result<bool, KeyvalueError> {
+        self.denied()
+    }
+
+    /// atomically add delta to the integer stored at a given key, returning the result
+    fn keyvalue_increment(&self, _key: &str, _delta: i64) -> Result<i64, KeyvalueError> {
+        self.denied()
+    }
+
+    /// get the payloads for the given keys, none for the keys that don't exist
+    fn keyvalue_get_many(&self, keys: &[&str]) -> Result<Vec<Option<Vec<u8>>>, KeyvalueError> {
+        self.store.keyvalue_get_many(keys)
+    }
+
+    /// set the payloads for the given keys
+    fn keyvalue_set_many(&self, _key_values: &[(&str, &[u8])]) -> Result<(), KeyvalueError> {
+        self.denied()
+    }
+
+    /// delete the payloads for the given keys
+    fn keyvalue_delete_many(&self, _keys: &[&str]) -> Result<(), KeyvalueError> {
+        self.denied()
+    }
+
+    /// list the keys in the store
+    fn keyvalue_keys(&self) -> Result<Vec<String>, KeyvalueError> {
+        self.store.keyvalue_keys()
+    }
+
+    /// list the keys in the store starting with the given prefix
+    fn keyvalue_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, KeyvalueError> {
+        self.store.keyvalue_keys_with_prefix(prefix)
+    }
+
+    /// list at most limit keys of the store, starting from the given cursor
+    fn keyvalue_keys_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<KeysPage, KeyvalueError> {
+        self.store.keyvalue_keys_page(cursor, limit)
+    }
+
+    /// delete the payload for a given key
+    fn keyvalue_delete(&self, _key: &str) -> Result<(), KeyvalueError> {
+        self.denied()
+    }
+
+    /// notify the watcher each time a key starting with its prefix changes
+    fn keyvalue_watch(&self, watcher: Watcher) -> Result<(), KeyvalueError> {
+        self.store.keyvalue_watch(watcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        channel_messages::OperationRequest,
+        keyvalue::{memory::MemoryImplementor, KeyvalueBackend},
+    };
+
+    /// A read-only store, and the store it wraps
+    fn open() -> (ReadOnlyStore, Box<dyn KeyvalueStore>) {
+        let mut backend = MemoryImplementor::default();
+        let inner = backend.keyvalue_open("test").unwrap();
+        inner.keyvalue_set("a", b"1").unwrap();
+        inner.keyvalue_set_with_ttl("b", b"2", 60).unwrap();
+        let store = ReadOnlyStore::new("test", backend.keyvalue_open("test").unwrap());
+        (store, inner)
+    }
+
+    fn denied<T>(result: Result<T, KeyvalueError>) -> bool {
+        matches!(result, Err(KeyvalueError::AuthenticationError(_)))
+    }
+
+    #[test]
+    fn writes_are_denied() {
+        let (store, inner) = open();
+        assert!(denied(store.keyvalue_set("a", b"x")));
+        assert!(denied(store.keyvalue_set("c", b"x")));
+        assert!(denied(store.keyvalue_set_many(&[("a", b"x"), ("c", b"x")])));
+        assert!(denied(store.keyvalue_set_with_ttl("a", b"x", 10)));
+        assert!(denied(store.keyvalue_delete("a")));
+        assert!(denied(store.keyvalue_delete_many(&["a", "b"])));
+        assert!(denied(store.keyvalue_increment("a", 1)));
+        assert!(denied(store.keyvalue_compare_and_swap(
+            "a",
+            Some(b"1"),
+            b"x"
+        )));
+
+        let mut keys = inner.keyvalue_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(inner.keyvalue_get("a").unwrap(), b"1");
+        assert_eq!(inner.keyvalue_get("b").unwrap(), b"2");
+        assert_eq!(inner.keyvalue_get_ttl("a").unwrap(), None);
+    }
+
+    #[test]
+    fn reads_pass_through() {
+        let (store, _inner) = open();
+        assert_eq!(store.keyvalue_get("a").unwrap(), b"1");
+        assert!(matches!(
+            store.keyvalue_get("c"),
+            Err(KeyvalueError::KeyNotFound(_))
+        ));
+        assert_eq!(store.keyvalue_get_ttl("a").unwrap(), None);
+        assert_eq!(store.keyvalue_get_ttl("b").unwrap(), Some(60));
+        assert_eq!(
+            store.keyvalue_get_many(&["a", "c", "b"]).unwrap(),
+            vec![Some(b"1".to_vec()), None, Some(b"2".to_vec())]
+        );
+
+        let mut keys = store.keyvalue_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(store.keyvalue_keys_with_prefix("b").unwrap(), vec!["b"]);
+        let page = store.keyvalue_keys_page(None, 1).unwrap();
+        assert_eq!(page.keys, vec!["a"]);
+        assert_eq!(page.next_cursor.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn watch_passes_through() {
+        let (store, inner) = open();
+        let (tx, rx) = crossbeam_channel::bounded(10);
+        store
+            .keyvalue_watch(Watcher {
+                prefix: "a".to_string(),
+                handler_name: "on-change".to_string(),
+                tx,
+            })
+            .unwrap();
+
+        inner.keyvalue_set("a", b"2").unwrap();
+        match rx.try_recv() {
+            Ok(OperationRequest::KeyvalueChanged { key, .. }) => assert_eq!(key, "a"),
+            _ => panic!("the change has not been notified"),
+        }
+    }
+}
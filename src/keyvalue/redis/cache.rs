@@ -0,0 +1,390 @@
+use log::debug;
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt,
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::RedisDriver;
+use crate::keyvalue::{
+    keyvalue::{KeysPage, KeyvalueError},
+    KeyvalueStore, Watcher,
+};
+
+/// A value read from Redis, together with the moment it has been read
+struct Entry {
+    value: Vec<u8>,
+    cached_at: Instant,
+}
+
+/// The reads from Redis of a key that is not cached
+struct PendingReads {
+    count: usize,
+    /// Whether the key has been invalidated since the oldest of these reads
+    /// started, in which case the values being read might be stale already
+    invalidated: bool,
+}
+
+struct Entries {
+    lru: LruCache<String, Entry>,
+    pending: HashMap<String, PendingReads>,
+}
+
+/// Keeps the most recently read values in memory, keyed by their Redis key.
+/// The values are kept for at most ttl, unless they are invalidated before.
+pub(super) struct Cache {
+    entries: Mutex<Entries>,
+    ttl: Duration,
+}
+
+impl Cache {
+    pub(super) fn new(size: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                lru: LruCache::new(size),
+                pending: HashMap::new(),
+            }),
+            ttl,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut entries = self.entries.lock();
+        match entries.lru.get(key) {
+            Some(entry) if entry.cached_at.elapsed() < self.ttl => Some(entry.value.clone()),
+            Some(_) => {
+                entries.lru.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Start reading a key from Redis, the value can then be cached through
+    /// the returned read
+    fn start_read(&self, key: String) -> PendingRead<'_> {
+        let mut entries = self.entries.lock();
+        entries
+            .pending
+            .entry(key.clone())
+            .or_insert(PendingReads {
+                count: 0,
+                invalidated: false,
+            })
+            .count += 1;
+        PendingRead { cache: self, key }
+    }
+
+    pub(super) fn invalidate(&self, key: &str) {
+        let mut entries = self.entries.lock();
+        entries.lru.pop(key);
+        if let Some(pending) = entries.pending.get_mut(key) {
+            pending.invalidated = true;
+        }
+    }
+
+    pub(super) fn clear(&self) {
+        let mut entries = self.entries.lock();
+        entries.lru.clear();
+        for pending in entries.pending.values_mut() {
+            pending.invalidated = true;
+        }
+    }
+}
+
+/// A value being read from Redis. It's cached only when its key has not been
+/// invalidated while it was being read.
+struct PendingRead<'a> {
+    cache: &'a Cache,
+    key: String,
+}
+
+impl PendingRead<'_> {
+    fn insert(self, value: Vec<u8>) {
+        let mut entries = self.cache.entries.lock();
+        let invalidated = entries
+            .pending
+            .get(&self.key)
+            .map_or(true, |pending| pending.invalidated);
+        if !invalidated {
+            entries.lru.put(
+                self.key.clone(),
+                Entry {
+                    value,
+                    cached_at: Instant::now(),
+                },
+            );
+        }
+    }
+}
+
+impl Drop for PendingRead<'_> {
+    fn drop(&mut self) {
+        let mut entries = self.cache.entries.lock();
+        if let Some(pending) = entries.pending.get_mut(&self.key) {
+            pending.count -= 1;
+            if pending.count == 0 {
+                entries.pending.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache").field("ttl", &self.ttl).finish()
+    }
+}
+
+/// Serves the reads of a container from the cache, falling back to Redis
+#[derive(Debug)]
+pub(super) struct CachedDriver {
+    driver: RedisDriver,
+    cache: Arc<Cache>,
+}
+
+impl CachedDriver {
+    pub(super) fn new(driver: RedisDriver, cache: Arc<Cache>) -> Self {
+        Self { driver, cache }
+    }
+
+    /// Run a command changing the given keys, then drop them from the cache.
+    /// This is done even when the command fails, since it might have been
+    /// applied anyway.
+    fn write<T>(
+        &self,
+        keys: &[&str],
+        f: impl FnOnce(&RedisDriver) -> Result<T, KeyvalueError>,
+    ) -> Result<T, KeyvalueError> {
+        let result = f(&self.driver);
+        for key in keys {
+            self.cache.invalidate(&self.driver.prefixed_key(key));
+        }
+        result
+    }
+}
+
+impl KeyvalueStore for CachedDriver {
+    /// get the payload for a given key
+    fn keyvalue_get(&self, key: &str) -> Result<Vec<u8>, KeyvalueError> {
+        let redis_key = self.driver.prefixed_key(key);
+        if let Some(value) = self.cache.get(&redis_key) {
+            debug!(key = key; "redis cache hit");
+            return Ok(value);
+        }
+
+        let read = self.cache.start_read(redis_key);
+        let value = self.driver.keyvalue_get(key)?;
+        read.insert(value.clone());
+        Ok(value)
+    }
+
+    /// set the payload for a given key
+    fn keyvalue_set(&self, key: &str, value: &[u8]) -> Result<(), KeyvalueError> {
+        self.write(&[key], |d| d.keyvalue_set(key, value))
+    }
+
+    /// set the payload for a given key, the key is deleted after ttl seconds
+    fn keyvalue_set_with_ttl(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: u64,
+    ) -> Result<(), KeyvalueError> {
+        self.write(&[key], |d| d.keyvalue_set_with_ttl(key, value, ttl))
+    }
+
+    /// get the seconds left before a given key expires, none if it never expires
+    fn keyvalue_get_ttl(&self, key: &str) -> Result<Option<u64>, KeyvalueError> {
+        self.driver.keyvalue_get_ttl(key)
+    }
+
+    /// atomically replace the payload for a given key, only if it matches the expected one
+    fn keyvalue_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, KeyvalueError> {
+        self.write(&[key], |d| d.keyvalue_compare_and_swap(key, expected, new))
+    }
+
+    /// atomically add delta to the integer stored at a given key, returning the result
+    fn keyvalue_increment(&self, key: &str, delta: i64) -> Result<i64, KeyvalueError> {
+        self.write(&[key], |d| d.keyvalue_increment(key, delta))
+    }
+
+    /// get the payloads for the given keys, none for the keys that don't exist
+    fn keyvalue_get_many(&self, keys: &[&str]) -> Result<Vec<Option<Vec<u8>>>, KeyvalueError> {
+        let mut values: Vec<Option<Vec<u8>>> = keys
+            .iter()
+            .map(|key| self.cache.get(&self.driver.prefixed_key(key)))
+            .collect();
+        let missing: Vec<&str> = keys
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(key, _)| *key)
+            .collect();
+        if missing.is_empty() {
+            return Ok(values);
+        }
+
+        let reads: Vec<PendingRead> = missing
+            .iter()
+            .map(|key| self.cache.start_read(self.driver.prefixed_key(key)))
+            .collect();
+        let fetched = self.driver.keyvalue_get_many(&missing)?;
+        let mut fetched = fetched.into_iter().zip(reads);
+        for value in values.iter_mut().filter(|value| value.is_none()) {
+            if let Some((Some(v), read)) = fetched.next() {
+                read.insert(v.clone());
+                *value = Some(v);
+            }
+        }
+        Ok(values)
+    }
+
+    /// set the payloads for the given keys
+    fn keyvalue_set_many(&self, key_values: &[(&str, &[u8])]) -> Result<(), KeyvalueError> {
+        let keys: Vec<&str> = key_values.iter().map(|(key, _)| *key).collect();
+        self.write(&keys, |d| d.keyvalue_set_many(key_values))
+    }
+
+    /// delete the payloads for the given keys
+    fn keyvalue_delete_many(&self, keys: &[&str]) -> Result<(), KeyvalueError> {
+        self.write(keys, |d| d.keyvalue_delete_many(keys))
+    }
+
+    /// list the keys in the store
+    fn keyvalue_keys(&self) -> Result<Vec<String>, KeyvalueError> {
+        self.driver.keyvalue_keys()
+    }
+
+    /// list the keys in the store starting with the given prefix
+    fn keyvalue_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, KeyvalueError> {
+        self.driver.keyvalue_keys_with_prefix(prefix)
+    }
+
+    /// list at most limit keys of the store, starting from the given cursor
+    fn keyvalue_keys_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<KeysPage, KeyvalueError> {
+        self.driver.keyvalue_keys_page(cursor, limit)
+    }
+
+    /// delete the payload for a given key
+    fn keyvalue_delete(&self, key: &str) -> Result<(), KeyvalueError> {
+        self.write(&[key], |d| d.keyvalue_delete(key))
+    }
+
+    /// notify the watcher each time a key starting with its prefix changes
+    fn keyvalue_watch(&self, watcher: Watcher) -> Result<(), KeyvalueError> {
+        self.driver.keyvalue_watch(watcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(size: usize, ttl: Duration) -> Cache {
+        Cache::new(NonZeroUsize::new(size).unwrap(), ttl)
+    }
+
+    fn read(cache: &Cache, key: &str, value: &[u8]) {
+        cache.start_read(key.to_string()).insert(value.to_vec());
+    }
+
+    #[test]
+    fn read_through() {
+        let cache = cache(10, Duration::from_secs(60));
+        assert_eq!(cache.get("a"), None);
+        read(&cache, "a", b"1");
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+
+        cache.invalidate("a");
+        assert_eq!(cache.get("a"), None);
+        assert!(cache.entries.lock().pending.is_empty());
+    }
+
+    #[test]
+    fn least_recently_used_are_evicted() {
+        let cache = cache(2, Duration::from_secs(60));
+        read(&cache, "a", b"1");
+        read(&cache, "b", b"2");
+        cache.get("a");
+        read(&cache, "c", b"3");
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+        assert_eq!(cache.get("c"), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn values_expire() {
+        let cache = cache(10, Duration::from_millis(20));
+        read(&cache, "a", b"1");
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get("a"), None);
+    }
+
+    #[test]
+    fn reads_racing_an_invalidation_are_not_cached() {
+        let cache = cache(10, Duration::from_secs(60));
+        let pending = cache.start_read("a".to_string());
+        cache.invalidate("a");
+        pending.insert(b"stale".to_vec());
+        assert_eq!(cache.get("a"), None);
+
+        // the following reads are cached again
+        read(&cache, "a", b"1");
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn invalidating_other_keys_keeps_the_reads() {
+        let cache = cache(10, Duration::from_secs(60));
+        let pending = cache.start_read("a".to_string());
+        cache.invalidate("b");
+        cache.invalidate("c");
+        pending.insert(b"1".to_vec());
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn concurrent_reads_of_an_invalidated_key() {
+        let cache = cache(10, Duration::from_secs(60));
+        let first = cache.start_read("a".to_string());
+        let second = cache.start_read("a".to_string());
+        cache.invalidate("a");
+        first.insert(b"stale".to_vec());
+        // the second read started before the invalidation too
+        second.insert(b"stale".to_vec());
+        assert_eq!(cache.get("a"), None);
+        assert!(cache.entries.lock().pending.is_empty());
+    }
+
+    #[test]
+    fn clear_drops_the_pending_reads() {
+        let cache = cache(10, Duration::from_secs(60));
+        read(&cache, "a", b"1");
+        let pending = cache.start_read("b".to_string());
+        cache.clear();
+        pending.insert(b"stale".to_vec());
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), None);
+    }
+
+    #[test]
+    fn failed_reads_are_released() {
+        let cache = cache(10, Duration::from_secs(60));
+        drop(cache.start_read("a".to_string()));
+        assert!(cache.entries.lock().pending.is_empty());
+    }
+}
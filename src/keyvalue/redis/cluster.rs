@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Result};
+use redis::{
+    cluster::{ClusterClient, ClusterClientBuilder, ClusterConnection, Connect},
+    ConnectionAddr, ConnectionInfo, ConnectionLike, IntoConnectionInfo, RedisResult, Value,
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::connection::Timeouts;
+
+/// How many times a command is sent again when the cluster redirects it to
+/// another node or fails to run it. This is enough to follow a `MOVED`
+/// redirection followed by an `ASK` one, during the migration of a slot.
+const RETRIES: u32 = 2;
+
+/// Shortest wait before sending again a command the cluster cannot run yet,
+/// in milliseconds
+const MIN_RETRY_WAIT: u64 = 10;
+
+/// Time allowed to connect to a node, in milliseconds. The cluster client
+/// connects to the nodes on its own, and gives no way to pass a timeout to
+/// each cluster, hence it's shared by all of them.
+static CONNECT_TIMEOUT: AtomicU64 = AtomicU64::new(0);
+
+/// A connection to a node of the cluster, made within the connect timeout
+pub(super) struct NodeConnection(redis::Connection);
+
+impl Connect for NodeConnection {
+    fn connect<T>(info: T, _timeout: Option<Duration>) -> RedisResult<Self>
+    where
+        T: IntoConnectionInfo,
+    {
+        let client = redis::Client::open(info)?;
+        let connection = match CONNECT_TIMEOUT.load(Ordering::Relaxed) {
+            0 => client.get_connection()?,
+            millis => client.get_connection_with_timeout(Duration::from_millis(millis))?,
+        };
+        Ok(Self(connection))
+    }
+
+    fn send_packed_command(&mut self, cmd: &[u8]) -> RedisResult<()> {
+        self.0.send_packed_command(cmd)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> RedisResult<()> {
+        self.0.set_write_timeout(dur)
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> RedisResult<()> {
+        self.0.set_read_timeout(dur)
+    }
+
+    fn recv_response(&mut self) -> RedisResult<Value> {
+        self.0.recv_response()
+    }
+}
+
+impl ConnectionLike for NodeConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        self.0.req_packed_command(cmd)
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.0.req_packed_commands(cmd, offset, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.0.get_db()
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.0.check_connection()
+    }
+
+    fn is_open(&self) -> bool {
+        self.0.is_open()
+    }
+}
+
+pub(super) type NodesConnection = ClusterConnection<NodeConnection>;
+
+/// A Redis Cluster, reached through some of its nodes
+#[derive(Clone)]
+pub(super) struct Cluster {
+    pub(super) client: ClusterClient,
+    nodes: Vec<ConnectionInfo>,
+}
+
+impl Cluster {
+    /// The nodes are `host[:port]` pairs, they are used to discover the
+    /// whole cluster. The credentials are taken from `connection_info`.
+    ///
+    /// The commands are retried a few times, waiting at most the I/O timeout
+    /// between the attempts, this bounds the time taken by each operation.
+    pub(super) fn new(
+        addresses: &[String],
+        connection_info: &ConnectionInfo,
+        timeouts: &Timeouts,
+    ) -> Result<Self> {
+        if connection_info.redis.db != 0 {
+            return Err(anyhow!(
+                "Redis Cluster supports only database 0, {} was requested",
+                connection_info.redis.db
+            ));
+        }
+
+        let nodes = addresses
+            .iter()
+            .map(|address| {
+                let mut node = format!("redis://{}/", address)
+                    .as_str()
+                    .into_connection_info()
+                    .map_err(|e| anyhow!("invalid redis cluster node {}: {}", address, e))?;
+                node.redis = connection_info.redis.clone();
+                Ok(node)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        CONNECT_TIMEOUT.store(timeouts.connect.as_millis() as u64, Ordering::Relaxed);
+        let client = ClusterClientBuilder::new(nodes.clone())
+            .retries(RETRIES)
+            .min_retry_wait(MIN_RETRY_WAIT)
+            .max_retry_wait(timeouts.io.as_millis() as u64)
+            .build()
+            .map_err(|e| anyhow!("invalid redis cluster configuration: {}", e))?;
+
+        Ok(Self { client, nodes })
+    }
+
+    pub(super) fn node_addresses(&self) -> Vec<String> {
+        self.nodes.iter().map(|n| n.addr.to_string()).collect()
+    }
+}
+
+/// Find the addresses of the master nodes, each one of them holds a part of the keyspace
+pub(super) fn masters(connection: &mut NodesConnection) -> RedisResult<Vec<ConnectionAddr>> {
+    // CLUSTER SLOTS replies with one entry per range of slots:
+    // [start slot, end slot, [master host, master port, ...], replicas...]
+    let ranges: Vec<Vec<redis::Value>> = redis::cmd("CLUSTER").arg("SLOTS").query(connection)?;
+
+    let mut masters: Vec<(String, u16)> = vec![];
+    for range in &ranges {
+        let master: Vec<redis::Value> = match range.get(2) {
+            Some(master) => redis::from_redis_value(master)?,
+            None => continue,
+        };
+        if let (Some(host), Some(port)) = (master.first(), master.get(1)) {
+            let addr = (
+                redis::from_redis_value(host)?,
+                redis::from_redis_value(port)?,
+            );
+            if !masters.contains(&addr) {
+                masters.push(addr);
+            }
+        }
+    }
+    Ok(masters
+        .into_iter()
+        .map(|(host, port)| ConnectionAddr::Tcp(host, port))
+        .collect())
+}
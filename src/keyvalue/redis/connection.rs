@@ -0,0 +1,747 @@
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use redis::{
+    ConnectionAddr, ConnectionInfo, ConnectionLike, ErrorKind, IntoConnectionInfo,
+    RedisConnectionInfo, RedisError,
+};
+
+use super::cluster::{self, Cluster, NodeConnection, NodesConnection};
+use std::{fmt, str::FromStr, time::Duration};
+
+/// Port used by Redis Sentinel when none is given
+const DEFAULT_SENTINEL_PORT: u16 = 26379;
+
+/// How long to wait for Redis before giving up. This prevents a degraded
+/// server from blocking the workers.
+#[derive(Clone, Copy)]
+pub(super) struct Timeouts {
+    /// Time allowed to establish a connection
+    pub(super) connect: Duration,
+    /// Time allowed to send a command and to receive its reply
+    pub(super) io: Duration,
+}
+
+/// Open a connection to a single Redis server
+fn open_connection(
+    connection_info: ConnectionInfo,
+    timeouts: &Timeouts,
+) -> Result<redis::Connection, RedisError> {
+    let connection =
+        redis::Client::open(connection_info)?.get_connection_with_timeout(timeouts.connect)?;
+    connection.set_read_timeout(Some(timeouts.io))?;
+    connection.set_write_timeout(Some(timeouts.io))?;
+    Ok(connection)
+}
+
+/// The Sentinel instances monitoring a Redis master
+#[derive(Clone)]
+pub(super) struct Sentinel {
+    sentinels: Vec<ConnectionInfo>,
+    master_name: String,
+}
+
+impl Sentinel {
+    /// Each address is either a `host[:port]` pair or a full `redis://` URL,
+    /// which can be used to provide the credentials of the Sentinel
+    pub(super) fn new(addresses: &[String], master_name: &str) -> Result<Self> {
+        if addresses.is_empty() {
+            return Err(anyhow!("At least one redis sentinel must be provided"));
+        }
+        let sentinels = addresses
+            .iter()
+            .map(|address| {
+                let url = if address.contains("://") {
+                    address.to_string()
+                } else if address.contains(':') {
+                    format!("redis://{}/", address)
+                } else {
+                    format!("redis://{}:{}/", address, DEFAULT_SENTINEL_PORT)
+                };
+                url.as_str()
+                    .into_connection_info()
+                    .map_err(|e| anyhow!("invalid redis sentinel address {}: {}", address, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            sentinels,
+            master_name: master_name.to_string(),
+        })
+    }
+
+    /// Ask the sentinels, one after the other, the address of the current master
+    fn master_addr(&self, timeouts: &Timeouts) -> Result<ConnectionAddr, RedisError> {
+        let mut last_error = None;
+        for sentinel in &self.sentinels {
+            match self.query_master_addr(sentinel, timeouts) {
+                Ok(addr) => return Ok(addr),
+                Err(e) => {
+                    warn!("cannot query redis sentinel at {}: {}", sentinel.addr, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| (ErrorKind::EmptySentinelList, "no sentinel configured").into()))
+    }
+
+    fn query_master_addr(
+        &self,
+        sentinel: &ConnectionInfo,
+        timeouts: &Timeouts,
+    ) -> Result<ConnectionAddr, RedisError> {
+        let mut connection = open_connection(sentinel.clone(), timeouts)?;
+
+        let addr: Option<(String, u16)> = redis::cmd("SENTINEL")
+            .arg("get-master-addr-by-name")
+            .arg(&self.master_name)
+            .query(&mut connection)?;
+        match addr {
+            Some((host, port)) => Ok(ConnectionAddr::Tcp(host, port)),
+            None => Err((
+                ErrorKind::MasterNameNotFoundBySentinel,
+                "unknown master",
+                self.master_name.clone(),
+            )
+                .into()),
+        }
+    }
+}
+
+/// Ensure the server on the other end of the connection is still the master.
+/// After a failover the old master comes back as a replica, which refuses writes.
+fn ensure_master(connection: &mut redis::Connection) -> Result<(), RedisError> {
+    // ROLE replies with an array, the first element is the role of the server
+    let reply: Vec<redis::Value> = redis::cmd("ROLE").query(connection)?;
+    match reply.first().map(redis::from_redis_value::<String>) {
+        Some(Ok(role)) if role == "master" => Ok(()),
+        _ => Err((
+            ErrorKind::ReadOnly,
+            "the redis server is not a master anymore",
+        )
+            .into()),
+    }
+}
+
+/// Number of keys Redis is asked to examine on each SCAN iteration
+const SCAN_COUNT: usize = 100;
+
+/// Go through all the keys of a server matching the given pattern
+fn scan(connection: &mut dyn ConnectionLike, pattern: &str) -> Result<Vec<String>, RedisError> {
+    // SCAN is used instead of KEYS, which blocks the server while
+    // going through the whole keyspace
+    let mut keys: Vec<String> = vec![];
+    let mut cursor: u64 = 0;
+    loop {
+        let (next_cursor, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .query(connection)?;
+        keys.extend(batch);
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+    Ok(keys)
+}
+
+/// Where a page of keys starts
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) struct ScanCursor {
+    /// Index of the master node being scanned, always 0 outside of a cluster
+    node: usize,
+    /// The cursor of the SCAN command
+    scan: u64,
+    /// Number of keys, of the ones returned by SCAN, that have already been
+    /// handed out. SCAN doesn't guarantee the number of keys it returns.
+    skip: usize,
+}
+
+impl FromStr for ScanCursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut parts = s.split('-').map(|p| p.parse::<u64>().map_err(|_| ()));
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(node), Some(scan), Some(skip), None) => Ok(Self {
+                node: node? as usize,
+                scan: scan?,
+                skip: skip? as usize,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for ScanCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.node, self.scan, self.skip)
+    }
+}
+
+/// Go through the keys of a server matching the given pattern, starting from
+/// the cursor, until limit keys are found. The cursor of the next page is
+/// returned, unless all the keys have been scanned.
+fn scan_page(
+    connection: &mut dyn ConnectionLike,
+    pattern: &str,
+    mut cursor: ScanCursor,
+    limit: usize,
+) -> Result<(Vec<String>, Option<ScanCursor>), RedisError> {
+    let mut keys: Vec<String> = vec![];
+    loop {
+        let (next_scan, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor.scan)
+            .arg("MATCH")
+            .arg(pattern)
+            // the COUNT must not change between pages, for the skipped keys
+            // to be the same ones that have been handed out
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .query(connection)?;
+
+        let wanted = limit - keys.len();
+        let available = batch.len().saturating_sub(cursor.skip);
+        if available > wanted {
+            // the next page continues from the same SCAN cursor
+            keys.extend(batch.into_iter().skip(cursor.skip).take(wanted));
+            cursor.skip += wanted;
+            return Ok((keys, Some(cursor)));
+        }
+        keys.extend(batch.into_iter().skip(cursor.skip));
+
+        if next_scan == 0 {
+            return Ok((keys, None));
+        }
+        cursor.scan = next_scan;
+        cursor.skip = 0;
+        if keys.len() == limit {
+            return Ok((keys, Some(cursor)));
+        }
+    }
+}
+
+/// Go through the keys of several servers, one after the other, starting from
+/// the cursor, until limit keys are found. `scan_node` finds at most the given
+/// number of keys of the server the cursor points to.
+fn scan_nodes(
+    nodes: usize,
+    mut cursor: ScanCursor,
+    limit: usize,
+    mut scan_node: impl FnMut(
+        ScanCursor,
+        usize,
+    ) -> Result<(Vec<String>, Option<ScanCursor>), RedisError>,
+) -> Result<(Vec<String>, Option<ScanCursor>), RedisError> {
+    let mut keys = vec![];
+    while cursor.node < nodes {
+        let (batch, next) = scan_node(cursor, limit - keys.len())?;
+        keys.extend(batch);
+        cursor = match next {
+            Some(next) => return Ok((keys, Some(next))),
+            None => ScanCursor {
+                node: cursor.node + 1,
+                ..ScanCursor::default()
+            },
+        };
+        if keys.len() == limit {
+            break;
+        }
+    }
+    let next = Some(cursor).filter(|c| c.node < nodes);
+    Ok((keys, next))
+}
+
+/// How the Redis servers are deployed
+#[derive(Clone)]
+pub(super) enum Topology {
+    /// A single server
+    Standalone,
+    /// A master, whose address is provided by Sentinel
+    Sentinel(Sentinel),
+    /// The keyspace is sharded between the masters of a cluster
+    Cluster(Cluster),
+}
+
+/// The actual connection to Redis
+enum Link {
+    Single(redis::Connection),
+    Cluster {
+        connection: Box<NodesConnection>,
+        /// Database and credentials, used to reach the single nodes
+        redis: RedisConnectionInfo,
+    },
+}
+
+/// A connection held by the r2d2 pool
+pub(super) struct RedisConnection {
+    link: Link,
+    timeouts: Timeouts,
+    /// The reply to a command that timed out might still arrive, and be
+    /// mistaken for the one of the next command. Because of that, the
+    /// connection is dropped instead of being returned to the pool.
+    timed_out: bool,
+}
+
+impl RedisConnection {
+    fn new(link: Link, timeouts: Timeouts) -> Self {
+        Self {
+            link,
+            timeouts,
+            timed_out: false,
+        }
+    }
+
+    /// Find all the keys matching the given pattern. In cluster mode all the
+    /// master nodes are scanned.
+    pub(super) fn keys_matching(&mut self, pattern: &str) -> Result<Vec<String>, RedisError> {
+        let timeouts = self.timeouts;
+        match &mut self.link {
+            Link::Single(_) => scan(self, pattern),
+            Link::Cluster { connection, redis } => {
+                let mut keys = vec![];
+                for addr in cluster::masters(connection)? {
+                    let connection_info = ConnectionInfo {
+                        addr,
+                        redis: redis.clone(),
+                    };
+                    let mut node = open_connection(connection_info, &timeouts)?;
+                    keys.extend(scan(&mut node, pattern)?);
+                }
+                // a key being migrated between two nodes is found on both
+                keys.sort_unstable();
+                keys.dedup();
+                Ok(keys)
+            }
+        }
+    }
+
+    /// Find at most limit keys matching the given pattern, starting from the
+    /// cursor. In cluster mode the master nodes are scanned one after the other.
+    pub(super) fn keys_page(
+        &mut self,
+        pattern: &str,
+        cursor: ScanCursor,
+        limit: usize,
+    ) -> Result<(Vec<String>, Option<ScanCursor>), RedisError> {
+        let timeouts = self.timeouts;
+        match &mut self.link {
+            Link::Single(_) if cursor.node == 0 => scan_page(self, pattern, cursor, limit),
+            Link::Single(_) => Ok((vec![], None)),
+            Link::Cluster { connection, redis } => {
+                let masters = cluster::masters(connection)?;
+                scan_nodes(masters.len(), cursor, limit, |cursor, limit| {
+                    let connection_info = ConnectionInfo {
+                        addr: masters[cursor.node].clone(),
+                        redis: redis.clone(),
+                    };
+                    let mut node = open_connection(connection_info, &timeouts)?;
+                    scan_page(&mut node, pattern, cursor, limit)
+                })
+            }
+        }
+    }
+
+    fn track_timeout<T>(&mut self, result: Result<T, RedisError>) -> Result<T, RedisError> {
+        if matches!(&result, Err(e) if e.is_timeout()) {
+            self.timed_out = true;
+        }
+        result
+    }
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> Result<redis::Value, RedisError> {
+        let result = match &mut self.link {
+            Link::Single(c) => c.req_packed_command(cmd),
+            Link::Cluster { connection, .. } => connection.req_packed_command(cmd),
+        };
+        self.track_timeout(result)
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<redis::Value>, RedisError> {
+        let result = match &mut self.link {
+            Link::Single(c) => c.req_packed_commands(cmd, offset, count),
+            Link::Cluster { connection, .. } => connection.req_packed_commands(cmd, offset, count),
+        };
+        self.track_timeout(result)
+    }
+
+    fn get_db(&self) -> i64 {
+        match &self.link {
+            Link::Single(c) => c.get_db(),
+            Link::Cluster { connection, .. } => connection.get_db(),
+        }
+    }
+
+    fn check_connection(&mut self) -> bool {
+        match &mut self.link {
+            Link::Single(c) => c.check_connection(),
+            Link::Cluster { connection, .. } => connection.check_connection(),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        !self.timed_out
+            && match &self.link {
+                Link::Single(c) => c.is_open(),
+                Link::Cluster { connection, .. } => connection.is_open(),
+            }
+    }
+}
+
+/// Creates the connections held by the r2d2 pool.
+///
+/// When Sentinel is used, the address of the master is looked up each time a
+/// new connection is made, and the pooled connections are checked to still
+/// point to the master before being handed out. This way the pool follows
+/// the failovers.
+#[derive(Clone)]
+pub(super) struct ConnectionManager {
+    /// Database and credentials to use. In Sentinel mode the address is
+    /// replaced with the one of the master.
+    connection_info: ConnectionInfo,
+    topology: Topology,
+    timeouts: Timeouts,
+}
+
+impl fmt::Display for ConnectionManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.topology {
+            Topology::Standalone => write!(f, "{}", self.connection_info.addr),
+            Topology::Sentinel(sentinel) => write!(
+                f,
+                "master '{}' via sentinels {}",
+                sentinel.master_name,
+                sentinel
+                    .sentinels
+                    .iter()
+                    .map(|s| s.addr.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Topology::Cluster(cluster) => {
+                write!(f, "cluster nodes {}", cluster.node_addresses().join(", "))
+            }
+        }
+    }
+}
+
+impl ConnectionManager {
+    pub(super) fn new(
+        connection_info: ConnectionInfo,
+        topology: Topology,
+        timeouts: Timeouts,
+    ) -> Self {
+        Self {
+            connection_info,
+            topology,
+            timeouts,
+        }
+    }
+
+    pub(super) fn is_cluster(&self) -> bool {
+        matches!(self.topology, Topology::Cluster(_))
+    }
+
+    pub(super) fn db(&self) -> i64 {
+        self.connection_info.redis.db
+    }
+
+    /// Open a connection that can be used to subscribe to channels, which
+    /// is not supported with Redis Cluster
+    pub(super) fn subscriber(&self) -> Result<redis::Connection, RedisError> {
+        match r2d2::ManageConnection::connect(self)?.link {
+            Link::Single(connection) => Ok(connection),
+            Link::Cluster { .. } => Err((
+                ErrorKind::ClientError,
+                "subscriptions are not supported with redis cluster",
+            )
+                .into()),
+        }
+    }
+}
+
+impl r2d2::ManageConnection for ConnectionManager {
+    type Connection = RedisConnection;
+    type Error = RedisError;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let link = match &self.topology {
+            Topology::Standalone => Link::Single(open_connection(
+                self.connection_info.clone(),
+                &self.timeouts,
+            )?),
+            Topology::Sentinel(sentinel) => {
+                let mut connection_info = self.connection_info.clone();
+                connection_info.addr = sentinel.master_addr(&self.timeouts)?;
+                debug!("connecting to redis master at {}", connection_info.addr);
+
+                let mut connection = open_connection(connection_info, &self.timeouts)?;
+                // the sentinels might not have noticed the failover yet
+                ensure_master(&mut connection)?;
+                Link::Single(connection)
+            }
+            Topology::Cluster(cluster) => {
+                let connection = cluster.client.get_generic_connection::<NodeConnection>()?;
+                connection.set_read_timeout(Some(self.timeouts.io))?;
+                connection.set_write_timeout(Some(self.timeouts.io))?;
+                Link::Cluster {
+                    connection: Box::new(connection),
+                    redis: self.connection_info.redis.clone(),
+                }
+            }
+        };
+        Ok(RedisConnection::new(link, self.timeouts))
+    }
+
+    fn is_valid(&self, connection: &mut Self::Connection) -> Result<(), Self::Error> {
+        match (&self.topology, &mut connection.link) {
+            (Topology::Sentinel(_), Link::Single(link)) => {
+                let result = ensure_master(link);
+                connection.track_timeout(result)
+            }
+            _ => redis::cmd("PING").query(connection),
+        }
+    }
+
+    fn has_broken(&self, connection: &mut Self::Connection) -> bool {
+        !connection.is_open()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Value;
+    use std::collections::HashMap;
+
+    /// Answers SCAN with the batch of keys registered for the given cursor
+    #[derive(Default)]
+    struct FakeScan {
+        batches: HashMap<u64, (u64, Vec<&'static str>)>,
+        requests: usize,
+    }
+
+    impl FakeScan {
+        fn new(batches: &[(u64, u64, &[&'static str])]) -> Self {
+            Self {
+                batches: batches
+                    .iter()
+                    .map(|(cursor, next, keys)| (*cursor, (*next, keys.to_vec())))
+                    .collect(),
+                requests: 0,
+            }
+        }
+    }
+
+    impl ConnectionLike for FakeScan {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> Result<Value, RedisError> {
+            // *6 $4 SCAN $len cursor ...
+            let cmd = std::str::from_utf8(cmd).unwrap();
+            let args: Vec<&str> = cmd.split("\r\n").collect();
+            assert_eq!(args[2], "SCAN");
+            let cursor: u64 = args[4].parse().unwrap();
+            self.requests += 1;
+            let (next, keys) = &self.batches[&cursor];
+            Ok(Value::Bulk(vec![
+                Value::Data(next.to_string().into_bytes()),
+                Value::Bulk(
+                    keys.iter()
+                        .map(|k| Value::Data(k.as_bytes().to_vec()))
+                        .collect(),
+                ),
+            ]))
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            _cmd: &[u8],
+            _offset: usize,
+            _count: usize,
+        ) -> Result<Vec<Value>, RedisError> {
+            unimplemented!()
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    fn cursor(node: usize, scan: u64, skip: usize) -> ScanCursor {
+        ScanCursor { node, scan, skip }
+    }
+
+    /// Go through all the pages, returning the keys of each one of them
+    fn all_pages(connection: &mut FakeScan, limit: usize) -> Vec<Vec<String>> {
+        let mut pages = vec![];
+        let mut next = Some(ScanCursor::default());
+        while let Some(cursor) = next {
+            let (keys, n) = scan_page(connection, "*", cursor, limit).unwrap();
+            pages.push(keys);
+            next = n;
+        }
+        pages
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        for c in [
+            ScanCursor::default(),
+            cursor(3, 17, 2),
+            cursor(0, u64::MAX, 99),
+        ] {
+            assert_eq!(c.to_string().parse::<ScanCursor>(), Ok(c));
+        }
+        assert_eq!(cursor(1, 2, 3).to_string(), "1-2-3");
+        for invalid in [
+            "", "1", "1-2", "1-2-3-4", "a-2-3", "1--3", "-1-2-3", "1-2-3 ",
+        ] {
+            assert!(invalid.parse::<ScanCursor>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn page_smaller_than_a_batch() {
+        let mut connection = FakeScan::new(&[(0, 7, &["a", "b", "c"]), (7, 0, &["d"])]);
+        let (keys, next) = scan_page(&mut connection, "*", ScanCursor::default(), 2).unwrap();
+        assert_eq!(keys, vec!["a", "b"]);
+        // the next page starts again from the same batch
+        assert_eq!(next, Some(cursor(0, 0, 2)));
+
+        let (keys, next) = scan_page(&mut connection, "*", next.unwrap(), 2).unwrap();
+        assert_eq!(keys, vec!["c", "d"]);
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn page_filled_exactly_before_the_end() {
+        let mut connection = FakeScan::new(&[(0, 7, &["a", "b"]), (7, 0, &["c"])]);
+        let (keys, next) = scan_page(&mut connection, "*", ScanCursor::default(), 2).unwrap();
+        assert_eq!(keys, vec!["a", "b"]);
+        // SCAN isn't over, the next page starts from its next cursor
+        assert_eq!(next, Some(cursor(0, 7, 0)));
+        assert_eq!(connection.requests, 1);
+    }
+
+    #[test]
+    fn page_filled_exactly_at_the_end() {
+        let mut connection = FakeScan::new(&[(0, 7, &["a"]), (7, 0, &["b"])]);
+        let (keys, next) = scan_page(&mut connection, "*", ScanCursor::default(), 2).unwrap();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn page_across_empty_batches() {
+        let mut connection = FakeScan::new(&[
+            (0, 3, &[]),
+            (3, 5, &["a"]),
+            (5, 9, &[]),
+            (9, 0, &["b", "c"]),
+        ]);
+        assert_eq!(
+            all_pages(&mut connection, 2),
+            vec![vec!["a", "b"], vec!["c"]]
+        );
+    }
+
+    #[test]
+    fn pages_hand_out_each_key_once() {
+        let batches: &[(u64, u64, &[&'static str])] = &[
+            (0, 11, &["a", "b", "c", "d", "e"]),
+            (11, 12, &["f"]),
+            (12, 0, &["g", "h", "i"]),
+        ];
+        for limit in 1..=10 {
+            let mut connection = FakeScan::new(batches);
+            let pages = all_pages(&mut connection, limit);
+            assert!(pages.iter().all(|p| p.len() <= limit));
+            assert_eq!(
+                pages.concat(),
+                vec!["a", "b", "c", "d", "e", "f", "g", "h", "i"],
+                "limit {}",
+                limit
+            );
+        }
+    }
+
+    /// Scan the given nodes like `keys_page` does in cluster mode
+    fn scan_fake_nodes(
+        nodes: &mut [FakeScan],
+        cursor: ScanCursor,
+        limit: usize,
+    ) -> (Vec<String>, Option<ScanCursor>) {
+        let count = nodes.len();
+        scan_nodes(count, cursor, limit, |cursor, limit| {
+            scan_page(&mut nodes[cursor.node], "*", cursor, limit)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn nodes_are_scanned_one_after_the_other() {
+        let mut nodes = [
+            FakeScan::new(&[(0, 0, &["a", "b", "c"])]),
+            FakeScan::new(&[(0, 0, &[])]),
+            FakeScan::new(&[(0, 4, &["d"]), (4, 0, &["e"])]),
+        ];
+
+        let (keys, next) = scan_fake_nodes(&mut nodes, ScanCursor::default(), 2);
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(next, Some(cursor(0, 0, 2)));
+
+        // the page continues on the next nodes, skipping the empty one
+        let (keys, next) = scan_fake_nodes(&mut nodes, next.unwrap(), 2);
+        assert_eq!(keys, vec!["c", "d"]);
+        assert_eq!(next, Some(cursor(2, 4, 0)));
+
+        let (keys, next) = scan_fake_nodes(&mut nodes, next.unwrap(), 2);
+        assert_eq!(keys, vec!["e"]);
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn node_filled_exactly() {
+        let mut nodes = [
+            FakeScan::new(&[(0, 0, &["a", "b"])]),
+            FakeScan::new(&[(0, 0, &["c"])]),
+        ];
+        let (keys, next) = scan_fake_nodes(&mut nodes, ScanCursor::default(), 2);
+        assert_eq!(keys, vec!["a", "b"]);
+        // the next page starts from the beginning of the next node
+        assert_eq!(next, Some(cursor(1, 0, 0)));
+        assert_eq!(nodes[1].requests, 0);
+
+        // the last node filled exactly ends the listing
+        let (keys, next) = scan_fake_nodes(&mut nodes, next.unwrap(), 1);
+        assert_eq!(keys, vec!["c"]);
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn cursor_past_the_last_node() {
+        // the cluster can lose a master between two pages
+        let mut nodes = [FakeScan::new(&[(0, 0, &["a"])])];
+        let (keys, next) = scan_fake_nodes(&mut nodes, cursor(1, 5, 0), 2);
+        assert!(keys.is_empty());
+        assert_eq!(next, None);
+    }
+}
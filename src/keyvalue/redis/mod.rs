@@ -0,0 +1,705 @@
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use r2d2::{ManageConnection, Pool};
+use redis::{Commands, ConnectionInfo, IntoConnectionInfo};
+use scheduled_thread_pool::ScheduledThreadPool;
+use std::{env, fmt, fs, num::NonZeroUsize, sync::Arc, time::Duration};
+
+use super::{
+    keyvalue::{KeysPage, KeyvalueError},
+    KeyvalueBackend, KeyvalueStore, Watcher, CONTAINER_FORBIDDEN_CHARS,
+};
+use crate::settings::Settings;
+
+mod cache;
+mod cluster;
+mod connection;
+mod notifications;
+use cache::{Cache, CachedDriver};
+use cluster::Cluster;
+use connection::{ConnectionManager, ScanCursor, Sentinel, Timeouts, Topology};
+use notifications::Notifications;
+
+/// Map the errors returned by Redis to the ones of the keyvalue interface
+fn redis_error(e: redis::RedisError) -> KeyvalueError {
+    if e.kind() == redis::ErrorKind::AuthenticationFailed
+        || matches!(
+            e.code(),
+            Some("NOAUTH") | Some("WRONGPASS") | Some("NOPERM")
+        )
+    {
+        KeyvalueError::AuthenticationError(e.to_string())
+    } else if e.is_timeout() {
+        KeyvalueError::TimeoutError(e.to_string())
+    } else if e.is_io_error()
+        || e.is_connection_refusal()
+        || e.is_connection_dropped()
+        || matches!(
+            e.kind(),
+            redis::ErrorKind::ReadOnly
+                | redis::ErrorKind::MasterDown
+                | redis::ErrorKind::BusyLoadingError
+                | redis::ErrorKind::TryAgain
+                | redis::ErrorKind::MasterNameNotFoundBySentinel
+        )
+    {
+        // These are expected while a failover is taking place
+        KeyvalueError::ConnectionError(e.to_string())
+    } else {
+        KeyvalueError::UnexpectedError(e.to_string())
+    }
+}
+
+/// The pool fails only when no connection became available before
+/// the checkout timeout expired. When the pool couldn't open a connection
+/// in the meantime, Redis is unreachable rather than slow, and the error of
+/// the last attempt is reported.
+fn pool_error(e: r2d2::Error) -> KeyvalueError {
+    // r2d2 only exposes the last connection error through its message
+    let message = e.to_string();
+    match message.strip_prefix("timed out waiting for connection: ") {
+        Some(connection_error) => {
+            KeyvalueError::ConnectionError(format!("cannot connect to redis: {}", connection_error))
+        }
+        None => KeyvalueError::TimeoutError(message),
+    }
+}
+
+/// Replace the value of KEYS[1] with ARGV[3], only if it's still ARGV[2]. When
+/// ARGV[1] is 0, the key must not exist instead. Lua scripts are run atomically.
+const COMPARE_AND_SWAP_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if ARGV[1] == '1' then
+    if current ~= ARGV[2] then
+        return 0
+    end
+elseif current then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[3])
+return 1
+"#;
+
+/// Escape the characters that have a special meaning inside of
+/// a Redis glob-style pattern
+fn escape_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The prefix shared by all the Redis keys of a container. The namespace and
+/// the container name never contain ':', '{' or '}', which are checked by the
+/// CLI and when opening the container: the prefixes of two containers can't
+/// overlap, and the hash tag spans the whole container.
+fn key_prefix(namespace: Option<&str>, container_name: &str, hash_tag: bool) -> String {
+    debug_assert!(!container_name.contains(CONTAINER_FORBIDDEN_CHARS));
+    let container = match namespace {
+        Some(namespace) => format!("{}:{}", namespace, container_name),
+        None => container_name.to_string(),
+    };
+    if hash_tag {
+        format!("{{{}}}:", container)
+    } else {
+        format!("{}:", container)
+    }
+}
+
+/// Turn the Redis keys back into the keys of the container
+fn strip_prefix(keys: Vec<String>, prefix: &str) -> Vec<String> {
+    keys.iter()
+        .filter_map(|k| k.strip_prefix(prefix))
+        .map(|k| k.to_string())
+        .collect()
+}
+
+pub struct RedisDriver {
+    container_name: String,
+    /// Prepended to the container name, to keep apart the data of the
+    /// applications sharing the same Redis server
+    namespace: Option<String>,
+    pool: Pool<ConnectionManager>,
+    /// Wrap the container name inside of a hash tag, to keep all the keys
+    /// of the container in the same slot of a Redis Cluster
+    hash_tag: bool,
+    notifications: Arc<Notifications>,
+}
+
+impl fmt::Debug for RedisDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisDriver")
+            .field("collection", &self.container_name)
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+impl RedisDriver {
+    fn new(
+        collection_name: &str,
+        namespace: Option<String>,
+        connection_pool: Pool<ConnectionManager>,
+        hash_tag: bool,
+        notifications: Arc<Notifications>,
+    ) -> Self {
+        Self {
+            container_name: collection_name.to_owned(),
+            namespace,
+            pool: connection_pool,
+            hash_tag,
+            notifications,
+        }
+    }
+
+    /// The prefix shared by all the Redis keys of the container
+    fn key_prefix(&self) -> String {
+        key_prefix(
+            self.namespace.as_deref(),
+            &self.container_name,
+            self.hash_tag,
+        )
+    }
+
+    /// The Redis key used to store the given key of the container
+    fn prefixed_key(&self, key: &str) -> String {
+        format!("{}{}", self.key_prefix(), key)
+    }
+}
+
+impl KeyvalueStore for RedisDriver {
+    /// get the payload for a given key
+    fn keyvalue_get(&self, key: &str) -> Result<Vec<u8>, KeyvalueError> {
+        debug!(key = key, container_name = self.container_name.as_str(); "redis get key");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        // Redis GET returns nil for non-existent keys, while an empty
+        // value is returned as an empty string
+        let val: Option<Vec<u8>> = client.get(self.prefixed_key(key)).map_err(redis_error)?;
+        val.ok_or_else(|| KeyvalueError::KeyNotFound(key.to_owned()))
+    }
+
+    /// set the payload for a given key
+    fn keyvalue_set(&self, key: &str, value: &[u8]) -> Result<(), KeyvalueError> {
+        debug!("redis set key");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        let key = self.prefixed_key(key);
+
+        client.set(key, value).map_err(redis_error)
+    }
+
+    /// set the payload for a given key, the key is deleted after ttl seconds
+    fn keyvalue_set_with_ttl(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: u64,
+    ) -> Result<(), KeyvalueError> {
+        debug!("redis set key with ttl");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        let key = self.prefixed_key(key);
+        redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("EX")
+            .arg(ttl)
+            .query(&mut *client)
+            .map_err(redis_error)
+    }
+
+    /// get the seconds left before a given key expires, none if it never expires
+    fn keyvalue_get_ttl(&self, key: &str) -> Result<Option<u64>, KeyvalueError> {
+        debug!("redis get ttl");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        // TTL replies with -2 when the key doesn't exist, and with -1
+        // when the key exists but doesn't expire
+        let ttl: i64 = client.ttl(self.prefixed_key(key)).map_err(redis_error)?;
+        match ttl {
+            -2 => Err(KeyvalueError::KeyNotFound(key.to_owned())),
+            ttl if ttl < 0 => Ok(None),
+            ttl => Ok(Some(ttl as u64)),
+        }
+    }
+
+    /// atomically replace the payload for a given key, only if it matches the expected one
+    fn keyvalue_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        new: &[u8],
+    ) -> Result<bool, KeyvalueError> {
+        debug!("redis compare and swap");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        redis::Script::new(COMPARE_AND_SWAP_SCRIPT)
+            .key(self.prefixed_key(key))
+            .arg(if expected.is_some() { "1" } else { "0" })
+            .arg(expected.unwrap_or_default())
+            .arg(new)
+            .invoke(&mut *client)
+            .map_err(redis_error)
+    }
+
+    /// atomically add delta to the integer stored at a given key, returning the result
+    fn keyvalue_increment(&self, key: &str, delta: i64) -> Result<i64, KeyvalueError> {
+        debug!("redis increment");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        client
+            .incr(self.prefixed_key(key), delta)
+            .map_err(|e| match e.kind() {
+                // the value is not an integer, or the result would overflow
+                redis::ErrorKind::ResponseError => KeyvalueError::InvalidValue(e.to_string()),
+                _ => redis_error(e),
+            })
+    }
+
+    /// get the payloads for the given keys, none for the keys that don't exist
+    fn keyvalue_get_many(&self, keys: &[&str]) -> Result<Vec<Option<Vec<u8>>>, KeyvalueError> {
+        debug!("redis get many keys");
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        // all the keys are fetched with a single round trip
+        let keys: Vec<String> = keys.iter().map(|k| self.prefixed_key(k)).collect();
+        redis::cmd("MGET")
+            .arg(keys)
+            .query(&mut *client)
+            .map_err(redis_error)
+    }
+
+    /// set the payloads for the given keys
+    fn keyvalue_set_many(&self, key_values: &[(&str, &[u8])]) -> Result<(), KeyvalueError> {
+        debug!("redis set many keys");
+        if key_values.is_empty() {
+            return Ok(());
+        }
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        let mut cmd = redis::cmd("MSET");
+        for (key, value) in key_values {
+            cmd.arg(self.prefixed_key(key)).arg(*value);
+        }
+        cmd.query(&mut *client).map_err(redis_error)
+    }
+
+    /// delete the payloads for the given keys
+    fn keyvalue_delete_many(&self, keys: &[&str]) -> Result<(), KeyvalueError> {
+        debug!("redis delete many keys");
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        let keys: Vec<String> = keys.iter().map(|k| self.prefixed_key(k)).collect();
+        client.del(keys).map_err(redis_error)
+    }
+
+    /// list the keys in the store
+    fn keyvalue_keys(&self) -> Result<Vec<String>, KeyvalueError> {
+        debug!("redis keys");
+        self.keyvalue_keys_with_prefix("")
+    }
+
+    /// list the keys in the store starting with the given prefix
+    fn keyvalue_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, KeyvalueError> {
+        debug!("redis keys with prefix");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        let container_prefix = self.key_prefix();
+        let pattern = format!("{}*", escape_pattern(&self.prefixed_key(prefix)));
+        let keys = client.keys_matching(&pattern).map_err(redis_error)?;
+        Ok(strip_prefix(keys, &container_prefix))
+    }
+
+    /// list at most limit keys of the store, starting from the given cursor
+    fn keyvalue_keys_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<KeysPage, KeyvalueError> {
+        debug!("redis keys page");
+        let cursor = match cursor {
+            Some(c) => c
+                .parse::<ScanCursor>()
+                .map_err(|_| KeyvalueError::InvalidValue(format!("invalid cursor: {}", c)))?,
+            None => ScanCursor::default(),
+        };
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        let prefix = self.key_prefix();
+        let pattern = format!("{}*", escape_pattern(&prefix));
+        let (keys, next_cursor) = client
+            .keys_page(&pattern, cursor, limit)
+            .map_err(redis_error)?;
+        Ok(KeysPage {
+            keys: strip_prefix(keys, &prefix),
+            next_cursor: next_cursor.map(|c| c.to_string()),
+        })
+    }
+
+    /// delete the payload for a given key
+    fn keyvalue_delete(&self, key: &str) -> Result<(), KeyvalueError> {
+        debug!("redis delete key");
+        let mut client = self.pool.get().map_err(pool_error)?;
+
+        let key = self.prefixed_key(key);
+        client.del(key).map_err(redis_error)
+    }
+
+    /// notify the watcher each time a key starting with its prefix changes
+    fn keyvalue_watch(&self, watcher: Watcher) -> Result<(), KeyvalueError> {
+        debug!("redis watch");
+        if self.hash_tag {
+            return Err(KeyvalueError::UnexpectedError(
+                "watching keys is not supported with redis cluster".to_string(),
+            ));
+        }
+        self.notifications.watch(self.key_prefix(), watcher);
+        Ok(())
+    }
+}
+
+pub struct RedisImplementor {
+    connection_pool: Pool<ConnectionManager>,
+    cluster: bool,
+    namespace: Option<String>,
+    /// Shared by all the containers, when the local cache is enabled
+    cache: Option<Arc<Cache>>,
+    /// Whether the cache is kept up to date with the keyspace notifications
+    cache_notifications: bool,
+    notifications: Arc<Notifications>,
+}
+
+impl RedisImplementor {
+    fn new(
+        manager: ConnectionManager,
+        max_pool_size: usize,
+        checkout_timeout: Duration,
+        namespace: Option<String>,
+        cache: Option<Arc<Cache>>,
+        cache_notifications: bool,
+        notifications: Arc<Notifications>,
+    ) -> Result<Self> {
+        info!(
+            "connecting to redis database: {} (db {})",
+            manager,
+            manager.db()
+        );
+
+        // Ensure the server is reachable and the credentials are right before
+        // starting the application
+        let mut connection = manager
+            .connect()
+            .map_err(|e| anyhow!("cannot connect to redis at {}: {}", manager, e))?;
+        redis::cmd("PING")
+            .query::<String>(&mut connection)
+            .map_err(|e| anyhow!("redis at {} did not reply to PING: {}", manager, e))?;
+
+        let thread_pool = Arc::new(
+            ScheduledThreadPool::builder()
+                .num_threads(max_pool_size)
+                .thread_name_pattern("r2d2-worker-{}")
+                .build(),
+        );
+
+        debug!("creating connection pool");
+        let cluster = manager.is_cluster();
+        let pool = r2d2::Pool::builder()
+            .thread_pool(thread_pool)
+            .connection_timeout(checkout_timeout)
+            .build(manager)
+            .map_err(|e| anyhow!("error building pool: {}", e))?;
+
+        Ok(Self {
+            connection_pool: pool,
+            cluster,
+            namespace,
+            cache,
+            cache_notifications,
+            notifications,
+        })
+    }
+}
+
+impl KeyvalueBackend for RedisImplementor {
+    fn keyvalue_open(&mut self, name: &str) -> Result<Box<dyn KeyvalueStore>, KeyvalueError> {
+        let driver = RedisDriver::new(
+            name,
+            self.namespace.clone(),
+            self.connection_pool.clone(),
+            self.cluster,
+            self.notifications.clone(),
+        );
+        match &self.cache {
+            Some(cache) => {
+                if self.cache_notifications {
+                    self.notifications.listen_to(&driver.key_prefix());
+                }
+                Ok(Box::new(CachedDriver::new(driver, cache.clone())))
+            }
+            None => Ok(Box::new(driver)),
+        }
+    }
+}
+
+/// Build the details needed to connect to Redis.
+///
+/// The location is either a full `redis://` URL or a Redis host, optionally
+/// followed by the port and the database number (e.g. `10.0.2.2:6380/2`).
+/// The credentials provided via a file or via the `REDIS_USERNAME` and
+/// `REDIS_PASSWORD` environment variables take precedence over the ones
+/// found inside of the URL, and the password file takes precedence over
+/// `REDIS_PASSWORD`. When Sentinel is used, the address of the master
+/// replaces the host of the location.
+fn connection_info(location: &str, settings: &Settings) -> Result<ConnectionInfo> {
+    build_connection_info(
+        location,
+        settings.redis_username.as_deref(),
+        settings.redis_password_file.as_deref(),
+        |name| env::var(name).ok(),
+    )
+}
+
+/// Build the details needed to connect to Redis, reading the environment
+/// variables with the given function
+fn build_connection_info(
+    location: &str,
+    username: Option<&str>,
+    password_file: Option<&str>,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> Result<ConnectionInfo> {
+    let url = if location.contains("://") {
+        location.to_string()
+    } else {
+        format!("redis://{}/", location)
+    };
+    let mut connection_info = url
+        .as_str()
+        .into_connection_info()
+        .map_err(|e| anyhow!("invalid redis connection parameters: {}", e))?;
+
+    if let Some(username) = username
+        .map(|u| u.to_string())
+        .or_else(|| env_var("REDIS_USERNAME"))
+    {
+        connection_info.redis.username = Some(username);
+    }
+
+    let password = match password_file {
+        Some(path) => Some(
+            fs::read_to_string(path)
+                .map_err(|e| anyhow!("cannot read redis password file {}: {}", path, e))?
+                .trim_end_matches(['\n', '\r'])
+                .to_string(),
+        ),
+        None => env_var("REDIS_PASSWORD"),
+    };
+    if password.is_some() {
+        connection_info.redis.password = password;
+    }
+
+    Ok(connection_info)
+}
+
+pub fn build_backend(
+    location: Option<&str>,
+    settings: &Settings,
+) -> Result<Box<dyn KeyvalueBackend>> {
+    let location = location
+        .or(settings.redis_url.as_deref())
+        .or(settings.redis_host.as_deref());
+    let discovered =
+        settings.redis_sentinel_master.is_some() || !settings.redis_cluster_nodes.is_empty();
+    let location = match location {
+        Some(location) => location,
+        // the addresses of the servers are discovered, the location is
+        // only needed to pick the database and the credentials
+        None if discovered => "localhost",
+        None => return Err(anyhow!("The redis connection parameter must be provided")),
+    };
+    let connection_info = connection_info(location, settings)?;
+
+    let timeouts = Timeouts {
+        connect: settings.redis_connect_timeout,
+        io: settings.redis_io_timeout,
+    };
+    let topology = if let Some(master_name) = &settings.redis_sentinel_master {
+        Topology::Sentinel(Sentinel::new(&settings.redis_sentinels, master_name)?)
+    } else if !settings.redis_cluster_nodes.is_empty() {
+        Topology::Cluster(Cluster::new(
+            &settings.redis_cluster_nodes,
+            &connection_info,
+            &timeouts,
+        )?)
+    } else {
+        Topology::Standalone
+    };
+
+    let manager = ConnectionManager::new(connection_info, topology, timeouts);
+    let cache = NonZeroUsize::new(settings.redis_cache_size)
+        .map(|size| Arc::new(Cache::new(size, settings.redis_cache_ttl)));
+
+    let notifications = Arc::new(Notifications::new(
+        manager.clone(),
+        cache.clone(),
+        settings.redis_namespace.clone(),
+    ));
+
+    let implementor = RedisImplementor::new(
+        manager,
+        settings.redis_thread_pool_size,
+        settings.redis_pool_timeout,
+        settings.redis_namespace.clone(),
+        cache,
+        settings.redis_cache_notifications,
+        notifications,
+    )?;
+
+    Ok(Box::new(implementor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_glob_characters() {
+        assert_eq!(escape_pattern("plain-key:1"), "plain-key:1");
+        assert_eq!(escape_pattern("a*b?c[d]e\\f"), "a\\*b\\?c\\[d\\]e\\\\f");
+        assert_eq!(escape_pattern(""), "");
+    }
+
+    #[test]
+    fn container_key_prefix() {
+        assert_eq!(key_prefix(None, "orders", false), "orders:");
+        assert_eq!(key_prefix(None, "orders", true), "{orders}:");
+        assert_eq!(
+            key_prefix(Some("my-app"), "orders", false),
+            "my-app:orders:"
+        );
+        assert_eq!(
+            key_prefix(Some("my-app"), "orders", true),
+            "{my-app:orders}:"
+        );
+    }
+
+    #[test]
+    fn strip_only_the_leading_prefix() {
+        let keys = vec![
+            "c:a".to_string(),
+            "c:c:b".to_string(),
+            "c:".to_string(),
+            "other:c:x".to_string(),
+        ];
+        assert_eq!(strip_prefix(keys, "c:"), vec!["a", "c:b", ""]);
+    }
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "REDIS_USERNAME" => Some("env-user".to_string()),
+            "REDIS_PASSWORD" => Some("env-password".to_string()),
+            _ => None,
+        }
+    }
+
+    /// A file holding the given password, followed by a newline
+    fn password_file(name: &str, password: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "hermit-wasm-redis-password-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, format!("{}\n", password)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn credentials_from_the_url() {
+        let info = build_connection_info("redis://u:p@h:6380/2", None, None, no_env).unwrap();
+        assert_eq!(info.redis.username.as_deref(), Some("u"));
+        assert_eq!(info.redis.password.as_deref(), Some("p"));
+        assert_eq!(info.redis.db, 2);
+
+        let info = build_connection_info("h:6380/2", None, None, no_env).unwrap();
+        assert_eq!(info.redis.username, None);
+        assert_eq!(info.redis.password, None);
+        assert_eq!(info.redis.db, 2);
+    }
+
+    #[test]
+    fn password_file_overrides_the_url() {
+        let path = password_file("file-over-url", "file-password");
+        let info = build_connection_info("redis://u:p@h/", None, Some(&path), no_env).unwrap();
+        assert_eq!(info.redis.username.as_deref(), Some("u"));
+        assert_eq!(info.redis.password.as_deref(), Some("file-password"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn environment_overrides_the_url() {
+        let info = build_connection_info("redis://u:p@h/", None, None, env).unwrap();
+        assert_eq!(info.redis.username.as_deref(), Some("env-user"));
+        assert_eq!(info.redis.password.as_deref(), Some("env-password"));
+
+        // the flag takes precedence over the environment
+        let info = build_connection_info("redis://u:p@h/", Some("flag-user"), None, env).unwrap();
+        assert_eq!(info.redis.username.as_deref(), Some("flag-user"));
+    }
+
+    #[test]
+    fn password_file_overrides_the_environment() {
+        let path = password_file("file-over-env", "file-password");
+        let info = build_connection_info("redis://u:p@h/", None, Some(&path), env).unwrap();
+        assert_eq!(info.redis.username.as_deref(), Some("env-user"));
+        assert_eq!(info.redis.password.as_deref(), Some("file-password"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_password_file() {
+        let path = std::env::temp_dir().join("hermit-wasm-redis-password-missing");
+        let result = build_connection_info("redis://u:p@h/", None, path.to_str(), env);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unreachable_redis_is_a_connection_error() {
+        // nothing listens on the port once the listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let connection_info =
+            build_connection_info(&format!("127.0.0.1:{}", port), None, None, no_env).unwrap();
+        let timeouts = Timeouts {
+            connect: Duration::from_millis(100),
+            io: Duration::from_millis(100),
+        };
+        let manager = ConnectionManager::new(connection_info, Topology::Standalone, timeouts);
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(500))
+            .build_unchecked(manager);
+
+        match pool.get().map_err(pool_error) {
+            Err(KeyvalueError::ConnectionError(e)) => {
+                assert!(e.starts_with("cannot connect to redis: "), "{}", e)
+            }
+            _ => panic!("expected a connection error"),
+        }
+    }
+}
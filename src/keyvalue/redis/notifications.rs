@@ -0,0 +1,237 @@
+use log::{info, warn};
+use parking_lot::RwLock;
+use redis::{PubSub, RedisError};
+use std::{
+    sync::{Arc, Once},
+    thread,
+    time::Duration,
+};
+
+use super::{cache::Cache, connection::ConnectionManager, escape_pattern};
+use crate::{keyvalue::Watcher, keyvalue_handler::ChangeKind};
+
+/// Time to wait before subscribing again to the keyspace notifications,
+/// after the connection to Redis has been lost
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// Time without any notification after which the connection is checked, by
+/// subscribing again to one of the patterns. The connection is considered lost
+/// when the reply doesn't come in time either.
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The pattern of the channels receiving the notifications of the keys
+/// starting with the given prefix
+fn pattern(channel_prefix: &str, key_prefix: &str) -> String {
+    format!("{}{}*", channel_prefix, escape_pattern(key_prefix))
+}
+
+/// Find out how a key has changed, given the name of the keyspace event.
+/// The events that don't change the payload, like setting the expiry of
+/// a key, are ignored.
+fn change_kind(event: &str) -> Option<ChangeKind> {
+    match event {
+        "del" | "expired" | "evicted" | "rename_from" => Some(ChangeKind::Delete),
+        "expire" | "persist" => None,
+        _ => Some(ChangeKind::Set),
+    }
+}
+
+/// Add a key prefix to the ones subscribed to. The prefixes never overlap, so
+/// that each notification is received once: the prefix is left out when an
+/// existing one already covers it, and replaces the existing ones it covers.
+fn add_prefix(prefixes: &mut Vec<String>, prefix: &str) {
+    if prefixes.iter().any(|p| prefix.starts_with(p.as_str())) {
+        return;
+    }
+    prefixes.retain(|p| !p.starts_with(prefix));
+    prefixes.push(prefix.to_string());
+}
+
+/// Receives the keyspace notifications of Redis, which tell when a key has
+/// changed, and forwards them to the local cache and to the watchers. These
+/// notifications must be enabled on the server, e.g. with
+/// `notify-keyspace-events KA`.
+pub(super) struct Notifications {
+    manager: ConnectionManager,
+    cache: Option<Arc<Cache>>,
+    /// When set, all the keys of the namespace are subscribed to at once
+    namespace: Option<String>,
+    /// The prefixes of the Redis keys whose notifications are received
+    prefixes: RwLock<Vec<String>>,
+    /// The watchers, together with the prefix of the Redis keys of their container
+    watchers: RwLock<Vec<(String, Watcher)>>,
+    listening: Once,
+}
+
+impl Notifications {
+    pub(super) fn new(
+        manager: ConnectionManager,
+        cache: Option<Arc<Cache>>,
+        namespace: Option<String>,
+    ) -> Self {
+        Self {
+            manager,
+            cache,
+            namespace,
+            prefixes: RwLock::new(vec![]),
+            watchers: RwLock::new(vec![]),
+            listening: Once::new(),
+        }
+    }
+
+    /// Receive the notifications of the keys starting with the given prefix,
+    /// or of all the keys of the namespace when there is one. Only these are
+    /// subscribed to, leaving out the keys of the other applications.
+    pub(super) fn listen_to(self: &Arc<Self>, key_prefix: &str) {
+        let prefix = match &self.namespace {
+            Some(namespace) => format!("{}:", namespace),
+            None => key_prefix.to_string(),
+        };
+        add_prefix(&mut self.prefixes.write(), &prefix);
+        self.start();
+    }
+
+    /// Subscribe to the notifications, unless this has already been done.
+    /// The subscription is made again each time the connection is lost.
+    fn start(self: &Arc<Self>) {
+        self.listening.call_once(|| {
+            let notifications = self.clone();
+            thread::Builder::new()
+                .name("redis-keyspace-notifications".to_string())
+                .spawn(move || loop {
+                    if let Err(e) = notifications.listen() {
+                        warn!("lost the redis keyspace notifications: {}", e);
+                    }
+                    // the notifications sent in the meantime are lost
+                    if let Some(cache) = &notifications.cache {
+                        cache.clear();
+                    }
+                    thread::sleep(RESUBSCRIBE_DELAY);
+                })
+                .expect("cannot start the redis keyspace notifications thread");
+        });
+    }
+
+    /// Notify the watcher of the changes made to the keys of a container
+    pub(super) fn watch(self: &Arc<Self>, key_prefix: String, watcher: Watcher) {
+        self.listen_to(&key_prefix);
+        self.watchers.write().push((key_prefix, watcher));
+    }
+
+    fn listen(&self) -> Result<(), RedisError> {
+        let mut connection = self.manager.subscriber()?;
+        // the notifications can be far apart, a timeout only means that
+        // the connection must be checked
+        connection.set_read_timeout(Some(PING_INTERVAL))?;
+
+        let mut pubsub = connection.as_pubsub();
+        let result = self.receive(&mut pubsub);
+        // leaving the subscribed state would wait for replies that may never
+        // come, the connection is dropped anyway
+        std::mem::forget(pubsub);
+        result
+    }
+
+    fn receive(&self, pubsub: &mut PubSub) -> Result<(), RedisError> {
+        let channel_prefix = format!("__keyspace@{}__:", self.manager.db());
+        let mut subscribed = vec![];
+        self.subscribe(pubsub, &channel_prefix, &mut subscribed)?;
+        info!("subscribed to the redis keyspace notifications");
+
+        loop {
+            match pubsub.get_message() {
+                Ok(message) => {
+                    if let Some(key) = message.get_channel_name().strip_prefix(&channel_prefix) {
+                        let event: String = message.get_payload()?;
+                        self.dispatch(key, &event);
+                    }
+                }
+                Err(e) if e.is_timeout() => {
+                    // subscribing again to a pattern is harmless, and fails
+                    // when the connection has been lost silently, e.g. after
+                    // a failover
+                    if !self.subscribe(pubsub, &channel_prefix, &mut subscribed)? {
+                        if let Some(prefix) = subscribed.first() {
+                            pubsub.psubscribe(pattern(&channel_prefix, prefix))?;
+                        }
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            self.subscribe(pubsub, &channel_prefix, &mut subscribed)?;
+        }
+    }
+
+    /// Bring the subscriptions in line with the registered prefixes.
+    /// Returns whether they have changed.
+    fn subscribe(
+        &self,
+        pubsub: &mut PubSub,
+        channel_prefix: &str,
+        subscribed: &mut Vec<String>,
+    ) -> Result<bool, RedisError> {
+        let prefixes = self.prefixes.read().clone();
+        if prefixes == *subscribed {
+            return Ok(false);
+        }
+        for prefix in prefixes.iter().filter(|p| !subscribed.contains(p)) {
+            pubsub.psubscribe(pattern(channel_prefix, prefix))?;
+        }
+        for prefix in subscribed.iter().filter(|p| !prefixes.contains(p)) {
+            pubsub.punsubscribe(pattern(channel_prefix, prefix))?;
+        }
+        *subscribed = prefixes;
+        // the keys changed before the subscription are unknown
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        Ok(true)
+    }
+
+    fn dispatch(&self, key: &str, event: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(key);
+        }
+        let kind = match change_kind(event) {
+            Some(kind) => kind,
+            None => return,
+        };
+        for (prefix, watcher) in self.watchers.read().iter() {
+            if let Some(key) = key.strip_prefix(prefix.as_str()) {
+                watcher.notify(key, kind);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_dont_overlap() {
+        let mut prefixes = vec![];
+        add_prefix(&mut prefixes, "app:users:");
+        add_prefix(&mut prefixes, "app:users:");
+        add_prefix(&mut prefixes, "app:usersettings:");
+        assert_eq!(prefixes, vec!["app:users:", "app:usersettings:"]);
+
+        add_prefix(&mut prefixes, "app:");
+        assert_eq!(prefixes, vec!["app:"]);
+        add_prefix(&mut prefixes, "app:sessions:");
+        assert_eq!(prefixes, vec!["app:"]);
+    }
+
+    #[test]
+    fn pattern_escapes_the_prefix() {
+        assert_eq!(
+            pattern("__keyspace@0__:", "my-app:"),
+            "__keyspace@0__:my-app:*"
+        );
+        assert_eq!(
+            pattern("__keyspace@2__:", "a*b[1]:"),
+            "__keyspace@2__:a\\*b\\[1\\]:*"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+// Based on the file generated by wit-import from wit/keyvalue-handler.wit, with
+// one small change to KeyvalueHandler attributes. See below
+
+#[allow(clippy::all)]
+#[allow(dead_code)]
+pub mod keyvalue_handler {
+    #[allow(unused_imports)]
+    use wit_bindgen_wasmi::{anyhow, wasmi};
+    /// How a watched key has changed
+    #[repr(u8)]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum ChangeKind {
+        /// the key has been created or its payload has changed
+        Set,
+        /// the key has been deleted, or it has expired
+        Delete,
+    }
+    impl core::fmt::Debug for ChangeKind {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            match self {
+                ChangeKind::Set => f.debug_tuple("ChangeKind::Set").finish(),
+                ChangeKind::Delete => f.debug_tuple("ChangeKind::Delete").finish(),
+            }
+        }
+    }
+
+    /// Auxiliary data associated with the wasm exports.
+    ///
+    /// This is required to be stored within the data of a
+    /// `Store<T>` itself so lifting/lowering state can be managed
+    /// when translating between the host and wasm.
+    #[derive(Default)]
+    pub struct KeyvalueHandlerData {}
+    pub struct KeyvalueHandler<T> {
+        // All these attributes are now public, that's because we need to
+        // set our own `handle_change` function, which points to the exported
+        // function whose name has been given to `keyvalue::watch`
+        pub get_state: Box<dyn Fn(&mut T) -> &mut KeyvalueHandlerData + Send + Sync>,
+        pub canonical_abi_free: wasmi::TypedFunc<(i32, i32, i32), ()>,
+        pub canonical_abi_realloc: wasmi::TypedFunc<(i32, i32, i32, i32), i32>,
+        pub handle_change: wasmi::TypedFunc<(i32, i32, i32), ()>,
+        pub memory: wasmi::Memory,
+    }
+    impl<T> KeyvalueHandler<T> {
+        #[allow(unused_variables)]
+
+        /// Adds any intrinsics, if necessary for this exported wasm
+        /// functionality to the `linker` provided.
+        ///
+        /// The `get_state` closure is required to access the
+        /// auxiliary data necessary for these wasm exports from
+        /// the general store's state.
+        pub fn add_to_linker(
+            linker: &mut wasmi::Linker<T>,
+            ctx: &mut impl wasmi::AsContextMut<UserState = T>,
+            get_state: impl Fn(&mut T) -> &mut KeyvalueHandlerData + Send + Sync + Copy + 'static,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        /// Instantiates the provided `module` using the specified
+        /// parameters, wrapping up the result in a structure that
+        /// translates between wasm and the host.
+        ///
+        /// The `linker` provided will have intrinsics added to it
+        /// automatically, so it's not necessary to call
+        /// `add_to_linker` beforehand. This function will
+        /// instantiate the `module` otherwise using `linker`, and
+        /// both an instance of this structure and the underlying
+        /// `wasmi::Instance` will be returned.
+        ///
+        /// The `get_state` parameter is used to access the
+        /// auxiliary state necessary for these wasm exports from
+        /// the general store state `T`.
+        pub fn instantiate(
+            mut store: impl wasmi::AsContextMut<UserState = T>,
+            module: &wasmi::Module,
+            linker: &mut wasmi::Linker<T>,
+            get_state: impl Fn(&mut T) -> &mut KeyvalueHandlerData + Send + Sync + Copy + 'static,
+        ) -> anyhow::Result<(Self, wasmi::Instance)> {
+            Self::add_to_linker(linker, &mut store, get_state)?;
+            let instance = linker.instantiate(&mut store, module)?.start(&mut store)?;
+            Ok((Self::new(store, &instance, get_state)?, instance))
+        }
+
+        /// Low-level creation wrapper for wrapping up the exports
+        /// of the `instance` provided in this structure of wasm
+        /// exports.
+        ///
+        /// This function will extract exports from the `instance`
+        /// defined within `store` and wrap them all up in the
+        /// returned structure which can be used to interact with
+        /// the wasm module.
+        pub fn new(
+            mut store: impl wasmi::AsContextMut<UserState = T>,
+            instance: &wasmi::Instance,
+            get_state: impl Fn(&mut T) -> &mut KeyvalueHandlerData + Send + Sync + Copy + 'static,
+        ) -> anyhow::Result<Self> {
+            let mut store = store.as_context_mut();
+            let canonical_abi_free =
+                instance.get_typed_func::<(i32, i32, i32), ()>(&mut store, "canonical_abi_free")?;
+            let canonical_abi_realloc = instance
+                .get_typed_func::<(i32, i32, i32, i32), i32>(&mut store, "canonical_abi_realloc")?;
+            let handle_change =
+                instance.get_typed_func::<(i32, i32, i32), ()>(&mut store, "handle-change")?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| anyhow::anyhow!("`memory` export not a memory"))?;
+            Ok(KeyvalueHandler {
+                canonical_abi_free,
+                canonical_abi_realloc,
+                handle_change,
+                memory,
+                get_state: Box::new(get_state),
+            })
+        }
+        pub fn handle_change(
+            &self,
+            mut caller: impl wasmi::AsContextMut<UserState = T>,
+            key: &str,
+            kind: ChangeKind,
+        ) -> Result<(), wasmi::core::Trap> {
+            let func_canonical_abi_realloc = &self.canonical_abi_realloc;
+            let memory = &self.memory;
+            let vec0 = key;
+            let ptr0 =
+                func_canonical_abi_realloc.call(&mut caller, (0, 0, 1, vec0.len() as i32))?;
+            memory
+                .data_mut(&mut caller)
+                .store_many(ptr0, vec0.as_bytes())?;
+            self.handle_change
+                .call(&mut caller, (ptr0, vec0.len() as i32, kind as i32))?;
+            Ok(())
+        }
+    }
+    use wit_bindgen_wasmi::rt::RawMem;
+}
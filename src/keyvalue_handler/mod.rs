@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Result};
+
+mod bindings;
+
+use bindings::keyvalue_handler as bindings_keyvalue_handler;
+pub use bindings_keyvalue_handler::ChangeKind;
+pub use bindings_keyvalue_handler::KeyvalueHandlerData;
+
+pub trait KeyvalueHandlerState {
+    /// Get the mutable reference to the keyvalue handler data.
+    fn get_keyvalue_handler_state_mut(&mut self) -> &mut KeyvalueHandlerData;
+}
+
+pub fn build_keyvalue_handler<T: KeyvalueHandlerState>(
+    handler_name: &str,
+    instance: &wasmi::Instance,
+    store: &mut wasmi::Store<T>,
+) -> Result<KeyvalueHandler<T>> {
+    KeyvalueHandler::new(handler_name, store, instance, |ctx| {
+        ctx.get_keyvalue_handler_state_mut()
+    })
+}
+
+pub struct KeyvalueHandler<T> {
+    inner: bindings_keyvalue_handler::KeyvalueHandler<T>,
+}
+
+impl<T> KeyvalueHandler<T> {
+    pub fn new(
+        handler_name: &str,
+        mut store: impl wasmi::AsContextMut<UserState = T>,
+        instance: &wasmi::Instance,
+        get_state: impl Fn(&mut T) -> &mut KeyvalueHandlerData + Send + Sync + Copy + 'static,
+    ) -> Result<Self> {
+        let mut store = store.as_context_mut();
+        let canonical_abi_free =
+            instance.get_typed_func::<(i32, i32, i32), ()>(&mut store, "canonical_abi_free")?;
+        let canonical_abi_realloc = instance
+            .get_typed_func::<(i32, i32, i32, i32), i32>(&mut store, "canonical_abi_realloc")?;
+        let handle_change = instance
+            .get_typed_func::<(i32, i32, i32), ()>(&mut store, handler_name)
+            .map_err(|e| {
+                anyhow!(
+                    "Error finding exported wasm function '{}': {:?}",
+                    handler_name,
+                    e
+                )
+            })?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow::anyhow!("`memory` export not a memory"))?;
+        Ok(Self {
+            inner: bindings_keyvalue_handler::KeyvalueHandler {
+                canonical_abi_free,
+                canonical_abi_realloc,
+                handle_change,
+                memory,
+                get_state: Box::new(get_state),
+            },
+        })
+    }
+
+    pub fn handle_change(
+        &self,
+        caller: impl wasmi::AsContextMut<UserState = T>,
+        key: &str,
+        kind: ChangeKind,
+    ) -> Result<(), wasmi::core::Trap> {
+        self.inner.handle_change(caller, key, kind)
+    }
+}
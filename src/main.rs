@@ -3,18 +3,22 @@ use hermit_sys as _;
 
 mod channel_messages;
 mod cli;
+mod event_loop;
 mod host_state;
 mod http_handler;
 mod http_server;
 mod keyvalue;
+mod keyvalue_handler;
 mod settings;
+mod wasm_module;
 
 use anyhow::Result;
+use channel_messages::OperationRequest;
 use host_state::HostState;
 use log::debug;
 use wasmi::*;
 
-use crate::http_server::start_http_server_loop;
+use crate::{event_loop::run_event_loop, http_server::start_http_server};
 
 fn main() -> Result<()> {
     let settings = match cli::parse_cli()? {
@@ -32,13 +36,17 @@ fn main() -> Result<()> {
 
     let engine = Engine::default();
 
-    // TODO: dirty workaround to get the WebAssembly module into
-    // the VM. Find a way to inject the `.wasm` file into the VM
-    // using another way
-    let module_bytes = include_bytes!("../wasm/http-server-demo.wasm");
+    let module_bytes = wasm_module::load_module(&settings)?;
+    if let Err(e) = wasm_module::verify_module(&module_bytes, &settings) {
+        eprintln!("Error: {}", e);
+        std::process::exit(wasm_module::VERIFICATION_FAILURE_EXIT_CODE);
+    }
     let module = Module::new(&engine, &mut &module_bytes[..])?;
 
-    let host_state = HostState::new(&settings)?;
+    // Used by the other threads to have the handlers of the module invoked
+    let (tx, rx) = crossbeam_channel::bounded::<OperationRequest>(100);
+
+    let host_state = HostState::new(&settings, tx.clone())?;
 
     let mut store = wasmi::Store::new(&engine, host_state);
 
@@ -59,9 +67,14 @@ fn main() -> Result<()> {
         .expect("something went wrong while calling 'main' function");
 
     let host_state = store.data();
-    if let Some(http_inner_server) = host_state.server() {
+    let http_inner_server = host_state.server();
+    let keyvalue_watching = host_state.keyvalue_watching();
+    if let Some(http_inner_server) = &http_inner_server {
+        start_http_server(http_inner_server, &settings, tx)?;
+    }
+    if http_inner_server.is_some() || keyvalue_watching {
         // This starts a loop
-        start_http_server_loop(&http_inner_server, &settings, &instance, &mut store)?;
+        run_event_loop(&rx, &instance, &mut store)?;
     }
 
     println!("Leaving");
@@ -0,0 +1,229 @@
+// Read the WebAssembly module from a file handed to the unikernel by QEMU at
+// boot time, next to the kernel image, through the fw_cfg device:
+//
+//   qemu-system-x86_64 ... -fw_cfg name=opt/hermit-wasm/module,file=app.wasm
+//
+// RustyHermit runs the application in ring 0, hence the I/O ports of the
+// device can be accessed directly.
+// See https://www.qemu.org/docs/master/specs/fw_cfg.html
+
+use anyhow::{anyhow, Result};
+use log::info;
+
+/// Selector of the item holding the "QEMU" signature
+const SIGNATURE_SELECTOR: u16 = 0x00;
+/// Selector of the item holding the list of the files
+const FILE_DIR_SELECTOR: u16 = 0x19;
+/// Size of the file names inside of the file list, including the NUL terminator
+const FILE_NAME_LEN: usize = 56;
+
+/// Access to the items of the fw_cfg device
+#[cfg_attr(
+    not(all(target_os = "hermit", target_arch = "x86_64")),
+    allow(dead_code)
+)]
+trait FwCfg {
+    /// Select the item to read, starting from its beginning
+    fn select(&mut self, selector: u16);
+
+    /// Read the next bytes of the selected item
+    fn read(&mut self, buf: &mut [u8]);
+}
+
+/// Load the WebAssembly module from the fw_cfg file with the given name.
+/// Modules bigger than `max_size` bytes are refused.
+#[cfg(all(target_os = "hermit", target_arch = "x86_64"))]
+pub(crate) fn read_module(name: &str, max_size: usize) -> Result<Vec<u8>> {
+    read_file(&mut ports::Ports, name, max_size)
+}
+
+/// Load the WebAssembly module from the fw_cfg file with the given name.
+/// This is possible only when running as a unikernel.
+#[cfg(not(all(target_os = "hermit", target_arch = "x86_64")))]
+pub(crate) fn read_module(name: &str, _max_size: usize) -> Result<Vec<u8>> {
+    Err(anyhow!(
+        "cannot read the WebAssembly module from the fw_cfg file {}: only supported by the x86_64 unikernel",
+        name
+    ))
+}
+
+#[cfg_attr(
+    not(all(target_os = "hermit", target_arch = "x86_64")),
+    allow(dead_code)
+)]
+fn read_file(fw_cfg: &mut impl FwCfg, name: &str, max_size: usize) -> Result<Vec<u8>> {
+    let mut signature = [0u8; 4];
+    fw_cfg.select(SIGNATURE_SELECTOR);
+    fw_cfg.read(&mut signature);
+    if &signature != b"QEMU" {
+        return Err(anyhow!(
+            "cannot read the WebAssembly module: no QEMU fw_cfg device found"
+        ));
+    }
+
+    let mut count = [0u8; 4];
+    fw_cfg.select(FILE_DIR_SELECTOR);
+    fw_cfg.read(&mut count);
+
+    // Each entry is made of: size (u32), selector (u16), reserved (u16), name,
+    // the integers are big endian
+    for _ in 0..u32::from_be_bytes(count) {
+        let mut entry = [0u8; 8 + FILE_NAME_LEN];
+        fw_cfg.read(&mut entry);
+
+        let entry_name = &entry[8..];
+        let entry_name = &entry_name[..entry_name
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(FILE_NAME_LEN)];
+        if entry_name != name.as_bytes() {
+            continue;
+        }
+
+        let size = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
+        let selector = u16::from_be_bytes([entry[4], entry[5]]);
+        if size > max_size {
+            return Err(anyhow!(
+                "the WebAssembly module is {} bytes, the maximum allowed size is {} bytes",
+                size,
+                max_size
+            ));
+        }
+
+        info!("loading WebAssembly module from fw_cfg file {}", name);
+        let mut module = vec![0u8; size];
+        fw_cfg.select(selector);
+        fw_cfg.read(&mut module);
+        return Ok(module);
+    }
+
+    Err(anyhow!(
+        "cannot read the WebAssembly module: fw_cfg file {} not found",
+        name
+    ))
+}
+
+#[cfg(all(target_os = "hermit", target_arch = "x86_64"))]
+mod ports {
+    use std::arch::asm;
+
+    /// The selector is written as a little endian u16
+    const SELECTOR_PORT: u16 = 0x510;
+    /// The contents of the selected item are read one byte at a time
+    const DATA_PORT: u16 = 0x511;
+
+    /// The I/O ports of the fw_cfg device
+    pub(super) struct Ports;
+
+    impl super::FwCfg for Ports {
+        fn select(&mut self, selector: u16) {
+            // SAFETY: the fw_cfg ports are only used by this module
+            unsafe {
+                asm!(
+                    "out dx, ax",
+                    in("dx") SELECTOR_PORT,
+                    in("ax") selector,
+                    options(nomem, nostack, preserves_flags)
+                );
+            }
+        }
+
+        fn read(&mut self, buf: &mut [u8]) {
+            for byte in buf.iter_mut() {
+                // SAFETY: the fw_cfg ports are only used by this module
+                unsafe {
+                    asm!(
+                        "in al, dx",
+                        out("al") *byte,
+                        in("dx") DATA_PORT,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fw_cfg device holding the given files
+    struct FakeFwCfg {
+        items: Vec<(u16, Vec<u8>)>,
+        selected: Vec<u8>,
+    }
+
+    impl FakeFwCfg {
+        fn new(files: &[(&str, &[u8])]) -> Self {
+            let mut dir = (files.len() as u32).to_be_bytes().to_vec();
+            let mut items = vec![(SIGNATURE_SELECTOR, b"QEMU".to_vec())];
+            for (i, (name, contents)) in files.iter().enumerate() {
+                let selector = 0x20 + i as u16;
+                dir.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+                dir.extend_from_slice(&selector.to_be_bytes());
+                dir.extend_from_slice(&[0, 0]);
+                let mut entry_name = name.as_bytes().to_vec();
+                entry_name.resize(FILE_NAME_LEN, 0);
+                dir.extend_from_slice(&entry_name);
+                items.push((selector, contents.to_vec()));
+            }
+            items.push((FILE_DIR_SELECTOR, dir));
+            Self {
+                items,
+                selected: vec![],
+            }
+        }
+    }
+
+    impl FwCfg for FakeFwCfg {
+        fn select(&mut self, selector: u16) {
+            self.selected = self
+                .items
+                .iter()
+                .find(|(s, _)| *s == selector)
+                .map(|(_, data)| data.clone())
+                .unwrap_or_default();
+        }
+
+        fn read(&mut self, buf: &mut [u8]) {
+            for byte in buf.iter_mut() {
+                *byte = if self.selected.is_empty() {
+                    0
+                } else {
+                    self.selected.remove(0)
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn read_named_file() {
+        let mut fw_cfg = FakeFwCfg::new(&[
+            ("etc/boot-menu-wait", &[0, 0]),
+            ("opt/hermit-wasm/module", b"\0asm module"),
+        ]);
+        let module = read_file(&mut fw_cfg, "opt/hermit-wasm/module", 1024).unwrap();
+        assert_eq!(module, b"\0asm module");
+    }
+
+    #[test]
+    fn missing_file() {
+        let mut fw_cfg = FakeFwCfg::new(&[("etc/boot-menu-wait", &[0, 0])]);
+        assert!(read_file(&mut fw_cfg, "opt/hermit-wasm/module", 1024).is_err());
+    }
+
+    #[test]
+    fn file_too_big() {
+        let mut fw_cfg = FakeFwCfg::new(&[("opt/hermit-wasm/module", &[0; 16])]);
+        assert!(read_file(&mut fw_cfg, "opt/hermit-wasm/module", 15).is_err());
+        assert!(read_file(&mut fw_cfg, "opt/hermit-wasm/module", 16).is_ok());
+    }
+
+    #[test]
+    fn no_device() {
+        let mut fw_cfg = FakeFwCfg::new(&[]);
+        fw_cfg.items.clear();
+        assert!(read_file(&mut fw_cfg, "opt/hermit-wasm/module", 1024).is_err());
+    }
+}
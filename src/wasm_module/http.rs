@@ -0,0 +1,335 @@
+// Minimal HTTP client used to download the WebAssembly module at boot time.
+// Only plain HTTP is supported, see the "No TLS support" section of the README.
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Errors that can happen while downloading the module
+enum FetchError {
+    /// The download can be attempted again
+    Transient(anyhow::Error),
+    /// Trying again is not going to help
+    Fatal(anyhow::Error),
+}
+
+/// Download the WebAssembly module from the given `http://` URL.
+///
+/// Transient failures are retried up to `retries` times, with an exponential
+/// backoff between the attempts. Modules bigger than `max_size` bytes are refused.
+pub(crate) fn fetch_module(url: &str, max_size: usize, retries: u32) -> Result<Vec<u8>> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        info!(
+            "downloading WebAssembly module from {} (attempt #{})",
+            url, attempt
+        );
+        match fetch(url, max_size) {
+            Ok(bytes) => return Ok(bytes),
+            Err(FetchError::Fatal(e)) => return Err(e),
+            Err(FetchError::Transient(e)) => {
+                if attempt > retries {
+                    return Err(e.context(format!(
+                        "cannot download WebAssembly module after {} attempts",
+                        attempt
+                    )));
+                }
+                warn!(
+                    "cannot download WebAssembly module: {}, retrying in {:?}",
+                    e, backoff
+                );
+                thread::sleep(backoff);
+                backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Split a `http://host[:port]/path` URL into its (host, port, path) components
+fn parse_url(url: &str) -> Result<(String, u16, String)> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("only http:// URLs are supported: {}", url))?;
+
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(anyhow!("URL without host: {}", url));
+    }
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|e| anyhow!("invalid port in URL {}: {}", url, e))?,
+        ),
+        None => (authority, 80),
+    };
+
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+fn fetch(url: &str, max_size: usize) -> std::result::Result<Vec<u8>, FetchError> {
+    let (host, port, path) = parse_url(url).map_err(FetchError::Fatal)?;
+
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|e| FetchError::Transient(anyhow!("cannot resolve {}: {}", host, e)))?
+        .next()
+        .ok_or_else(|| FetchError::Transient(anyhow!("cannot resolve {}", host)))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .map_err(|e| FetchError::Transient(anyhow!("cannot connect to {}: {}", addr, e)))?;
+    stream
+        .set_read_timeout(Some(IO_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+        .map_err(|e| FetchError::Transient(anyhow!("cannot set socket timeouts: {}", e)))?;
+
+    // The port can be omitted only when it's the default one, see RFC 7230
+    let host_header = if port == 80 {
+        host.clone()
+    } else {
+        format!("{}:{}", host, port)
+    };
+    // HTTP/1.0 is used on purpose, this way the server will not use
+    // chunked transfer encoding
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: hermit-wasm\r\nAccept: application/wasm\r\n\r\n",
+        path, host_header
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| FetchError::Transient(anyhow!("cannot send request: {}", e)))?;
+
+    let mut reader = BufReader::new(stream);
+
+    let mut status_line = String::new();
+    reader
+        .read_line(&mut status_line)
+        .map_err(|e| FetchError::Transient(anyhow!("cannot read response: {}", e)))?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| {
+            FetchError::Transient(anyhow!("invalid HTTP status line: {:?}", status_line))
+        })?;
+    match status {
+        200 => {}
+        500..=599 => {
+            return Err(FetchError::Transient(anyhow!(
+                "server replied with status {}",
+                status
+            )))
+        }
+        _ => {
+            return Err(FetchError::Fatal(anyhow!(
+                "server replied with status {}",
+                status
+            )))
+        }
+    }
+
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .map_err(|e| FetchError::Transient(anyhow!("cannot read response headers: {}", e)))?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    if let Some(len) = content_length {
+        if len > max_size {
+            return Err(FetchError::Fatal(anyhow!(
+                "the WebAssembly module is {} bytes, the maximum allowed size is {} bytes",
+                len,
+                max_size
+            )));
+        }
+    }
+
+    let mut body = Vec::with_capacity(content_length.unwrap_or_default());
+    reader
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| FetchError::Transient(anyhow!("cannot read response body: {}", e)))?;
+
+    if body.len() > max_size {
+        return Err(FetchError::Fatal(anyhow!(
+            "the WebAssembly module exceeds the maximum allowed size of {} bytes",
+            max_size
+        )));
+    }
+    if let Some(len) = content_length {
+        if body.len() != len {
+            return Err(FetchError::Transient(anyhow!(
+                "truncated response: received {} bytes out of {}",
+                body.len(),
+                len
+            )));
+        }
+    }
+
+    debug!("downloaded {} bytes", body.len());
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Start a stand-in HTTP server answering each connection with the next
+    /// response, returns the URL of the module and the requests received
+    fn serve(responses: Vec<&'static [u8]>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://127.0.0.1:{}/module.wasm",
+            listener.local_addr().unwrap().port()
+        );
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                requests.push(request);
+                reader.get_mut().write_all(response).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn download() {
+        let (url, server) = serve(vec![b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello"]);
+        assert_eq!(fetch_module(&url, 1024, 0).unwrap(), b"hello");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /module.wasm HTTP/1.0\r\n"));
+        let port = url.split(':').nth(2).unwrap().split('/').next().unwrap();
+        assert!(requests[0].contains(&format!("Host: 127.0.0.1:{}\r\n", port)));
+    }
+
+    #[test]
+    fn download_without_content_length() {
+        let (url, server) = serve(vec![b"HTTP/1.0 200 OK\r\n\r\nhello"]);
+        assert_eq!(fetch_module(&url, 1024, 0).unwrap(), b"hello");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn server_errors_are_retried() {
+        let (url, server) = serve(vec![
+            b"HTTP/1.0 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+        ]);
+        assert_eq!(fetch_module(&url, 1024, 1).unwrap(), b"hello");
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn server_errors_exhaust_retries() {
+        let (url, server) = serve(vec![
+            b"HTTP/1.0 500 Internal Server Error\r\n\r\n",
+            b"HTTP/1.0 500 Internal Server Error\r\n\r\n",
+        ]);
+        assert!(fetch_module(&url, 1024, 1).is_err());
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let (url, server) = serve(vec![b"HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n"]);
+        let err = fetch_module(&url, 1024, 3).unwrap_err();
+        assert_eq!(err.to_string(), "server replied with status 404");
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn content_length_over_max_size() {
+        let (url, server) = serve(vec![b"HTTP/1.0 200 OK\r\nContent-Length: 2048\r\n\r\n"]);
+        let err = fetch_module(&url, 1024, 3).unwrap_err();
+        assert!(err.to_string().contains("maximum allowed size"));
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn body_over_max_size() {
+        let (url, server) = serve(vec![b"HTTP/1.0 200 OK\r\n\r\n0123456789"]);
+        assert!(fetch_module(&url, 4, 3).is_err());
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn truncated_body_is_retried() {
+        let (url, server) = serve(vec![
+            b"HTTP/1.0 200 OK\r\nContent-Length: 10\r\n\r\nhello",
+            b"HTTP/1.0 200 OK\r\nContent-Length: 10\r\n\r\nhelloworld",
+        ]);
+        assert!(matches!(
+            fetch(&url, 1024),
+            Err(FetchError::Transient(e)) if e.to_string().contains("truncated")
+        ));
+        assert_eq!(fetch_module(&url, 1024, 0).unwrap(), b"helloworld");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn parse_urls() {
+        assert_eq!(
+            parse_url("http://example.com/module.wasm").unwrap(),
+            ("example.com".to_string(), 80, "/module.wasm".to_string())
+        );
+        assert_eq!(
+            parse_url("http://10.0.2.2:8080/a/b.wasm?v=1").unwrap(),
+            ("10.0.2.2".to_string(), 8080, "/a/b.wasm?v=1".to_string())
+        );
+        assert_eq!(
+            parse_url("http://example.com").unwrap(),
+            ("example.com".to_string(), 80, "/".to_string())
+        );
+        assert_eq!(
+            parse_url("http://example.com:81").unwrap(),
+            ("example.com".to_string(), 81, "/".to_string())
+        );
+        assert!(parse_url("https://example.com/module.wasm").is_err());
+        assert!(parse_url("example.com/module.wasm").is_err());
+        assert!(parse_url("http:///module.wasm").is_err());
+        assert!(parse_url("http://example.com:http/module.wasm").is_err());
+        assert!(parse_url("http://example.com:65536/module.wasm").is_err());
+    }
+}
@@ -0,0 +1,44 @@
+mod boot;
+mod http;
+mod verify;
+
+pub(crate) use verify::verify_module;
+
+use crate::settings::Settings;
+
+use anyhow::{anyhow, Result};
+use log::info;
+
+/// The SpiderLightning demo application, used when no module is given
+/// by the user
+const EMBEDDED_MODULE: &[u8] = include_bytes!("../../wasm/http-server-demo.wasm");
+
+/// Exit code used when the WebAssembly module does not pass verification
+pub(crate) const VERIFICATION_FAILURE_EXIT_CODE: i32 = 3;
+
+/// Load the bytes of the WebAssembly module to be run.
+///
+/// The module is read from the path provided via the `--module` flag,
+/// downloaded from the URL provided via the `--module-url` flag, or read
+/// from the QEMU fw_cfg file provided via the `--module-fw-cfg` flag.
+/// When none of them is given, the embedded demo module is used.
+pub(crate) fn load_module(settings: &Settings) -> Result<Vec<u8>> {
+    if let Some(url) = &settings.module_url {
+        return http::fetch_module(url, settings.module_max_size, settings.module_fetch_retries);
+    }
+    if let Some(name) = &settings.module_fw_cfg {
+        return boot::read_module(name, settings.module_max_size);
+    }
+
+    match &settings.module {
+        Some(path) => {
+            info!("loading WebAssembly module from {}", path);
+            std::fs::read(path)
+                .map_err(|e| anyhow!("cannot read WebAssembly module {}: {}", path, e))
+        }
+        None => {
+            info!("no WebAssembly module provided, using the embedded demo");
+            Ok(EMBEDDED_MODULE.to_vec())
+        }
+    }
+}
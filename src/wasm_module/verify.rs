@@ -0,0 +1,170 @@
+use crate::settings::Settings;
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use log::{debug, info};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Returned when the WebAssembly module cannot be trusted
+#[derive(Debug)]
+pub(crate) struct VerificationError(String);
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WebAssembly module verification failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+/// Ensure the WebAssembly module can be trusted.
+///
+/// The module is compared against the SHA-256 digest provided via the
+/// `--module-sha256` flag. When an Ed25519 public key is given via the
+/// `--module-public-key` flag, the module must also come with a valid
+/// detached signature, provided via the `--module-signature` flag.
+pub(crate) fn verify_module(bytes: &[u8], settings: &Settings) -> Result<(), VerificationError> {
+    verify(
+        bytes,
+        settings.module_sha256.as_deref(),
+        settings.module_public_key.as_deref(),
+        settings.module_signature.as_deref(),
+    )
+}
+
+fn verify(
+    bytes: &[u8],
+    sha256: Option<&str>,
+    public_key: Option<&str>,
+    signature: Option<&str>,
+) -> Result<(), VerificationError> {
+    if let Some(expected) = sha256 {
+        verify_sha256(bytes, expected)?;
+    }
+
+    match (public_key, signature) {
+        (Some(public_key), Some(signature)) => verify_signature(bytes, public_key, signature),
+        (Some(_), None) => Err(VerificationError(
+            "a public key has been provided, but the module is not signed".to_string(),
+        )),
+        (None, Some(_)) => Err(VerificationError(
+            "the module is signed, but no public key has been provided".to_string(),
+        )),
+        (None, None) => Ok(()),
+    }
+}
+
+fn verify_sha256(bytes: &[u8], expected: &str) -> Result<(), VerificationError> {
+    let digest = hex::encode(Sha256::digest(bytes));
+    if !digest.eq_ignore_ascii_case(expected) {
+        return Err(VerificationError(format!(
+            "SHA-256 digest is {}, expected {}",
+            digest, expected
+        )));
+    }
+    info!("WebAssembly module matches the pinned SHA-256 digest");
+    Ok(())
+}
+
+fn verify_signature(
+    bytes: &[u8],
+    public_key: &str,
+    signature: &str,
+) -> Result<(), VerificationError> {
+    let public_key: [u8; 32] = hex::decode(public_key)
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| {
+            VerificationError("the public key must be 32 bytes, hex encoded".to_string())
+        })?;
+    let public_key = VerifyingKey::from_bytes(&public_key)
+        .map_err(|e| VerificationError(format!("invalid public key: {}", e)))?;
+
+    let signature: [u8; 64] = hex::decode(signature)
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .ok_or_else(|| {
+            VerificationError("the signature must be 64 bytes, hex encoded".to_string())
+        })?;
+    let signature = Signature::from_bytes(&signature);
+
+    debug!("verifying Ed25519 signature of the WebAssembly module");
+    public_key
+        .verify_strict(bytes, &signature)
+        .map_err(|e| VerificationError(format!("invalid signature: {}", e)))?;
+    info!("WebAssembly module signature is valid");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    /// The hex encoded public key and signature of the module
+    fn sign(module: &[u8]) -> (String, String) {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        (
+            hex::encode(signing_key.verifying_key().to_bytes()),
+            hex::encode(signing_key.sign(module).to_bytes()),
+        )
+    }
+
+    #[test]
+    fn nothing_to_verify() {
+        assert!(verify(MODULE, None, None, None).is_ok());
+    }
+
+    #[test]
+    fn sha256_digest() {
+        let digest = hex::encode(Sha256::digest(MODULE));
+        assert!(verify(MODULE, Some(&digest), None, None).is_ok());
+        assert!(verify(MODULE, Some(&digest.to_uppercase()), None, None).is_ok());
+
+        let other = hex::encode(Sha256::digest(b"other module"));
+        assert!(verify(MODULE, Some(&other), None, None).is_err());
+        assert!(verify(MODULE, Some("not a digest"), None, None).is_err());
+        assert!(verify(MODULE, Some(&digest[..63]), None, None).is_err());
+    }
+
+    #[test]
+    fn valid_signature() {
+        let (public_key, signature) = sign(MODULE);
+        assert!(verify(MODULE, None, Some(&public_key), Some(&signature)).is_ok());
+
+        let digest = hex::encode(Sha256::digest(MODULE));
+        assert!(verify(MODULE, Some(&digest), Some(&public_key), Some(&signature)).is_ok());
+    }
+
+    #[test]
+    fn tampered_module() {
+        let (public_key, signature) = sign(MODULE);
+        let mut tampered = MODULE.to_vec();
+        tampered.push(0);
+        assert!(verify(&tampered, None, Some(&public_key), Some(&signature)).is_err());
+    }
+
+    #[test]
+    fn malformed_public_key() {
+        let (public_key, signature) = sign(MODULE);
+        assert!(verify(MODULE, None, Some(&public_key[2..]), Some(&signature)).is_err());
+        assert!(verify(MODULE, None, Some("not hex"), Some(&signature)).is_err());
+    }
+
+    #[test]
+    fn malformed_signature() {
+        let (public_key, signature) = sign(MODULE);
+        assert!(verify(MODULE, None, Some(&public_key), Some(&signature[2..])).is_err());
+        assert!(verify(MODULE, None, Some(&public_key), Some("not hex")).is_err());
+    }
+
+    #[test]
+    fn key_and_signature_go_together() {
+        let (public_key, signature) = sign(MODULE);
+        assert!(verify(MODULE, None, Some(&public_key), None).is_err());
+        assert!(verify(MODULE, None, None, Some(&signature)).is_err());
+    }
+}